@@ -1,39 +1,77 @@
-use std::io::{Read, Write};
+use std::io::{self, Read, Write};
+use std::collections::{HashMap, HashSet};
 use std::net::{TcpListener, TcpStream, SocketAddr, Shutdown};
 use std::str::{from_utf8};
-use rand::Rng;
+use std::thread;
+use std::time::{Duration, Instant};
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use log::{debug, info, warn};
+use crate::consensus::{Agreement, Vote, ROUND_TIMEOUT, ROUNDS_PER_PHASE, grade, king, phases, propose};
+use crate::epoch::Update;
+use crate::turncoat::Turn;
+use crate::value::NetworkValue;
 
+pub const MAX_FREEZE : Duration = Duration::from_secs(5);     // Longest freeze an agent accepts, so that no client can hang it
 
-pub struct Agent{
+
+pub struct Agent<V : NetworkValue>{
     pub id : u16,                       // Identifier (port number)
-    value : [u8; 2],                    // Value to send stored in a byte array
+    pub identity : String,              // Stable identifier, kept across restarts and games unlike the port, see new_identity()
+    values : HashMap<String, V>,        // Value to send for every key, encoded when answering
+    truths : HashMap<String, V>,        // Real value of every key, to lie about it or to tell it once turned
+    epochs : HashMap<String, u64>,      // Epoch of the value of every key, 0 until the first update
     listener : TcpListener,             // TCP socket
+    started : Instant,                  // Creation time, to answer ping with the uptime
+    liar_keys : HashSet<String>,        // Keys the agent lies about, it also lies during agreements on them
+    max_value : V,
+    rng : StdRng,                       // Draws the lies sent during agreements
+    votes : Vec<Vote<V>>,               // Votes received for agreements in progress or not started yet
+    finished : HashSet<u64>,            // Sessions of the agreements done, their late votes are dropped
+    stopping : bool,                    // Set when "stop" is received during an agreement
+    neighbors : Option<Vec<u16>>,       // Ports of the agents it talks to, every agent if None
+    queries : u64,                      // Number of "talk" and "read" answered
+    turn : Option<(Turn, HashSet<String>)>,     // When the agent changes sides on these keys, None once it did
+}
+
+pub fn new_identity() -> String {
+    /*
+        Returns a new random identity, 16 hexadecimal digits. It is not drawn from the game seed, so
+        it does not change the liars and their values.
+    */
+    format!("{:016x}", rand::random::<u64>())
 }
 
-impl Agent{
-    pub fn new (real_value : u16, max_value : u16, liar : bool) -> Self {
+impl<V : NetworkValue> Agent<V>{
+    pub fn new (keys : Vec<(String, V, bool)>, max_value : &V, seed : u64) -> Self {
         /*
             Creates a new agent. 
                 - id is any available port on which the agent listens.
-                - value is real_value if liar is false otherwhise a random value between 1.. 
-                  and max_value which is not real_value
+                - identity is a new one (see new_identity()), the network gives it the identity kept
+                  from a previous game or launch if any.
+                - for every key, the value is the real value if the agent does not lie about this key,
+                  otherwhise a random value which is not the real value (see NetworkValue::lie)
+            The value of key is sent on "talk <key>", the value of the empty key on "talk".
 
             Args : 
-                - real_value : target value
+                - keys : (key, real value, true if the agent must lie about this key)
                 - max_value : expected value
-                - liar : true if the agent must lie
+                - seed : seed of the agent's random number generator
             Returns :
                 - Agent
         */
-        let mut val = real_value;
-
-        // If the agent lies, value is a random u16 which is not real_value
-        if liar {
-            let mut rng = rand::thread_rng();
-            val = rng.gen_range(1..max_value) as u16; 
-            if val >= real_value{  
-                val = val + 1;
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut values = HashMap::new();
+        let mut liar_keys = HashSet::new();
+        let truths = keys.iter().map(|(key, real_value, _)| (key.clone(), real_value.clone())).collect();
+
+        for (key, real_value, liar) in keys {
+            // If the agent lies, value is a random value which is not real_value
+            let val = if liar { real_value.lie(max_value, &mut rng) } else { real_value };
+            if liar {
+                liar_keys.insert(key.clone());
             }
+            values.insert(key, val);
         }
         
         let addrs = SocketAddr::from(([127, 0, 0, 1], 0));  // Ports 0 will find any available port
@@ -42,17 +80,96 @@ impl Agent{
 
         Self {
             id : listener.local_addr().unwrap().port(),
-            value : val.to_be_bytes(),           
-            listener : listener,
+            identity : new_identity(),
+            epochs : values.keys().map(|key| (key.clone(), 0)).collect(),
+            values,
+            truths,
+            listener,
+            started : Instant::now(),
+            liar_keys,
+            max_value : max_value.clone(),
+            rng,
+            votes : Vec::new(),
+            finished : HashSet::new(),
+            stopping : false,
+            neighbors : None,
+            queries : 0,
+            turn : None,
         }
     } 
 
+    pub fn set_turn(&mut self, turn : Turn, keys : HashSet<String>) {
+        /*
+            Makes the agent change sides on keys when turn is due : a traitor starts lying about the keys
+            it was honest about, a reformed liar starts telling the truth (see turncoat.rs).
+        */
+        self.turn = Some((turn, keys));
+    }
 
-    pub fn handle_connection(&self, mut stream : TcpStream) -> bool{
+    fn turn_if_due(&mut self) {
+        /*
+            Changes sides once the turn is due, before answering a query.
+        */
+        let due = match &self.turn {
+            Some((Turn::After(queries), _)) => self.queries > *queries,
+            Some((Turn::At(after), _)) => self.started.elapsed() >= *after,
+            None => false,
+        };
+        if !due {
+            return;
+        }
+        let (_, keys) = self.turn.take().expect("A turn is due");
+        for key in keys {
+            let truth = match self.truths.get(&key) {
+                Some(truth) => truth.clone(),
+                None => continue,
+            };
+            if self.liar_keys.remove(&key) {
+                info!("Agent {}: reformed, tells the truth about {:?}", self.id, key);
+                self.values.insert(key, truth);
+            } else {
+                info!("Agent {}: traitor, lies about {:?}", self.id, key);
+                let lie = truth.lie(&self.max_value, &mut self.rng);
+                self.values.insert(key.clone(), lie);
+                self.liar_keys.insert(key);
+            }
+        }
+    }
+
+
+    fn read_message(stream : &mut TcpStream) -> io::Result<Vec<u8>> {
+        /*
+            Reads a message. "agree", "vote", "neighbors" and "update" messages may be long, their sender closes
+            its side of the connection once sent so they are read until the end.
+        */
+        let mut buffer = [0_u8; 1024];
+        let size = stream.read(&mut buffer)?;
+        let mut msg = buffer[..size].to_vec();
+        if msg.starts_with(b"agree ") || msg.starts_with(b"vote ") || msg.starts_with(b"neighbors ") || msg.starts_with(b"update ") {
+            stream.read_to_end(&mut msg)?;
+        }
+        Ok(msg)
+    }
+
+    pub fn handle_connection(&mut self, mut stream : TcpStream) -> bool{
         /*
             Reads the value received from the client.
-            If the value is "talk" it sends self.value and returns false
+            If the value is "talk" it sends its value and returns false
+            If the value is "talk <key>" it sends its value for key and returns false. Nothing is sent for an unknown key.
+            If the value is "read" or "read <key>" it sends the epoch of its value in 8 bytes big endian, then
+            its value, and returns false.
+            If the value is "update <Update>" it takes the value of a newer epoch, a liar lies about it, and
+            returns false (see epoch.rs).
+            If the value is "ping" it sends "pong <id> <uptime in ms>" and returns false.
+            If the value is "whoami" it sends its identity and returns false.
+            If the value is "agree <Agreement>" it runs the agreement with the other agents, sends the
+            decided value and returns false (see consensus.rs).
+            If the value is "vote <Vote>" it keeps the vote for its agreement and returns false.
+            If the value is "neighbors <ports>" it replaces its neighbors by the JSON list of ports and returns false.
+            If the value is "freeze <ms>" it stops handling messages during ms milliseconds, at most MAX_FREEZE, and returns false.
             If the value is "stop" it returns true without answering.
+            A message which is not UTF-8 is dropped.
+            Before answering "talk" or "read", a traitor or a reformed liar changes sides if its turn is due.
             
             Args : 
                 - stream : TCP stream with the client
@@ -62,53 +179,311 @@ impl Agent{
 
         let mut stop = false;
 
-        let mut buffer = [0 as u8; 1024];
-        match stream.read(& mut buffer){
+        match Self::read_message(&mut stream){
 
-            Ok(size) => {
-                let msg = from_utf8(&buffer[..size]).expect("");
-                match msg {
-                    "talk" => {
-                        stream.write(&self.value).unwrap();
+            Ok(buffer) => {
+                let msg = match from_utf8(&buffer) {
+                    Ok(msg) => msg,
+                    Err(e) => {
+                        warn!("Agent {}: dropped a message which is not UTF-8 : {}", self.id, e);
+                        return false;
+                    }
+                };
+                #[cfg(test)]
+                self.handle_test_message(msg);
+                let mut words = msg.splitn(2, ' ');
+                let command = words.next().unwrap_or("");
+                if command == "talk" || command == "read" {
+                    self.queries += 1;
+                    self.turn_if_due();
+                }
+                match (command, words.next()) {
+                    ("talk", key) => {
+                        let key = key.unwrap_or("");
+                        match self.values.get(key) {
+                            Some(value) => {
+                                if let Err(e) = stream.write_all(&value.encode()) {
+                                    warn!("Agent {}: failed to answer : {}", self.id, e);
+                                }
+                            },
+                            None => warn!("Agent {}: Received talk for unknown key : {}", self.id, key),
+                        }
+                    },
+                    ("read", key) => {
+                        let key = key.unwrap_or("");
+                        match (self.values.get(key), self.epochs.get(key)) {
+                            (Some(value), Some(epoch)) => {
+                                let answer = [epoch.to_be_bytes().to_vec(), value.encode()].concat();
+                                if let Err(e) = stream.write_all(&answer) {
+                                    warn!("Agent {}: failed to answer read : {}", self.id, e);
+                                }
+                            },
+                            _ => warn!("Agent {}: Received read for unknown key : {}", self.id, key),
+                        }
+                    },
+                    ("update", Some(update)) => {
+                        match serde_json::from_str::<Update<V>>(update) {
+                            Ok(update) => self.update(update),
+                            Err(e) => warn!("Agent {}: Received incorrect update {} : {}", self.id, update, e),
+                        }
+                    },
+                    ("ping", None) => {
+                        let pong = format!("pong {} {}", self.id, self.started.elapsed().as_millis());
+                        if let Err(e) = stream.write_all(pong.as_bytes()) {
+                            warn!("Agent {}: failed to answer ping : {}", self.id, e);
+                        }
+                    },
+                    ("whoami", None) => {
+                        if let Err(e) = stream.write_all(self.identity.as_bytes()) {
+                            warn!("Agent {}: failed to answer whoami : {}", self.id, e);
+                        }
+                    },
+                    ("agree", Some(agreement)) => {
+                        match serde_json::from_str::<Agreement>(agreement) {
+                            Ok(agreement) => {
+                                if let Some(value) = self.agree(&agreement) {
+                                    if let Err(e) = stream.write_all(&value.encode()) {
+                                        warn!("Agent {}: failed to answer agree : {}", self.id, e);
+                                    }
+                                }
+                            },
+                            Err(e) => warn!("Agent {}: Received incorrect agreement {} : {}", self.id, agreement, e),
+                        }
+                    },
+                    ("vote", Some(vote)) => {
+                        match serde_json::from_str::<Vote<V>>(vote) {
+                            Ok(vote) if self.finished.contains(&vote.session) => debug!("Agent {}: late vote from {}", self.id, vote.from),
+                            Ok(vote) => self.votes.push(vote),
+                            Err(e) => warn!("Agent {}: Received incorrect vote {} : {}", self.id, vote, e),
+                        }
+                    },
+                    ("neighbors", Some(ports)) => {
+                        match serde_json::from_str::<Vec<u16>>(ports) {
+                            Ok(ports) => self.set_neighbors(ports),
+                            Err(e) => warn!("Agent {}: Received incorrect neighbors {} : {}", self.id, ports, e),
+                        }
+                    },
+                    ("freeze", Some(ms)) => {
+                        match ms.parse::<u64>() {
+                            Ok(ms) => {
+                                let duration = Duration::from_millis(ms).min(MAX_FREEZE);
+                                debug!("Agent {}: frozen for {} ms", self.id, duration.as_millis());
+                                thread::sleep(duration);
+                            },
+                            Err(_) => warn!("Agent {}: Received incorrect freeze : {}", self.id, ms),
+                        }
                     },
-                    "stop" => stop = true,
+                    ("stop", None) => stop = true,
                     _ => {
-                        println!("Agent {}: Received incorrect message : {}", self.id, msg);
+                        warn!("Agent {}: Received incorrect message : {}", self.id, msg);
                     },
                 }
             },
 
             Err(e) => {
-                stream.shutdown(Shutdown::Both).unwrap();
-                println!("Error handle connection {}", e);
+                let _ = stream.shutdown(Shutdown::Both);
+                warn!("Agent {}: error handling connection {}", self.id, e);
             }
         }
         stop
     }
 
-    pub fn run(&mut self) -> (){
+    #[cfg(test)]
+    fn handle_test_message(&self, msg : &str) {
+        /*
+            Messages only understood in the tests, before the protocol : "crash" makes the agent panic,
+            to check that the supervisor restarts it.
+        */
+        if msg == "crash" {
+            panic!("Agent {}: crash asked", self.id);
+        }
+    }
+
+    pub fn update(&mut self, update : Update<V>) {
+        /*
+            Takes the value of a key pushed by the source if its epoch is newer than the agent's one.
+            A liar about the key takes a new lie about the new value.
+        */
+        match self.epochs.get_mut(&update.key) {
+            Some(epoch) if *epoch < update.epoch => {
+                *epoch = update.epoch;
+                self.truths.insert(update.key.clone(), update.value.clone());
+                let value = match self.liar_keys.contains(&update.key) {
+                    true => update.value.lie(&self.max_value, &mut self.rng),
+                    false => update.value,
+                };
+                debug!("Agent {}: {:?} is {} at epoch {}", self.id, update.key, value, update.epoch);
+                self.values.insert(update.key, value);
+            },
+            Some(_) => debug!("Agent {}: stale update of {:?} at epoch {}", self.id, update.key, update.epoch),
+            None => warn!("Agent {}: Received update for unknown key : {}", self.id, update.key),
+        }
+    }
+
+    pub fn set_neighbors(&mut self, neighbors : Vec<u16>) {
+        /*
+            Sets the ports of the agents this agent talks to, see topology.rs.
+        */
+        debug!("Agent {}: {} neighbor(s)", self.id, neighbors.len());
+        self.neighbors = Some(neighbors);
+    }
+
+    pub fn neighbors(&self) -> Option<&[u16]> {
+        /*
+            Returns the ports of the agents this agent talks to, None if it talks to every agent.
+        */
+        self.neighbors.as_deref()
+    }
+
+    fn local_peers(&self, peers : &[u16]) -> Vec<u16> {
+        /*
+            Returns the agents of peers this agent talks to : itself and its neighbors.
+        */
+        match &self.neighbors {
+            Some(neighbors) => peers.iter().filter(|&&p| p == self.id || neighbors.contains(&p)).cloned().collect(),
+            None => peers.to_vec(),
+        }
+    }
+
+    fn send_votes(&mut self, agreement : &Agreement, round : usize, to : &[u16], value : &Option<V>) {
+        /*
+            Sends "vote <Vote>" to every agent of to. A liar sends a different random value to every agent.
+        */
+        let liar = self.liar_keys.contains(&agreement.key);
+        for &port in to {
+            let value = match value {
+                Some(value) if liar => Some(value.lie(&self.max_value, &mut self.rng)),
+                _ => value.clone(),
+            };
+            let vote = Vote { session : agreement.session, round, from : self.id, value };
+            let msg = format!("vote {}", serde_json::to_string(&vote).expect("A vote is serializable"));
+            let addrs = SocketAddr::from(([127, 0, 0, 1], port));
+            let sent = TcpStream::connect(addrs).and_then(|mut stream| {
+                stream.write_all(msg.as_bytes())?;
+                stream.shutdown(Shutdown::Write)
+            });
+            if let Err(e) = sent {
+                debug!("Agent {}: failed to send vote to {} : {}", self.id, port, e);
+            }
+        }
+    }
+
+    fn collect(&mut self, session : u64, round : usize, from : &[u16]) -> Vec<Option<V>> {
+        /*
+            Waits for the votes of round from every agent of from, at most ROUND_TIMEOUT.
+            Other messages received meanwhile are handled as usual.
+
+            Returns :
+                - Vec<Option<V>> : vote of every agent which voted in time, one per agent
+        */
+        let deadline = Instant::now() + ROUND_TIMEOUT;
+        let received = |votes : &Vec<Vote<V>>| votes.iter().filter(|v| v.session == session && v.round == round && from.contains(&v.from)).count();
+
+        self.listener.set_nonblocking(true).expect("Cannot set non-blocking");
+        while received(&self.votes) < from.len() && Instant::now() < deadline {
+            match self.listener.accept() {
+                Ok((stream, _)) => {
+                    stream.set_nonblocking(false).expect("Cannot set blocking");
+                    if self.handle_connection(stream) {
+                        self.stopping = true;
+                    }
+                },
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => thread::sleep(Duration::from_millis(1)),
+                Err(e) => warn!("Agent {}: connection failed : {}", self.id, e),
+            }
+        }
+        self.listener.set_nonblocking(false).expect("Cannot set blocking");
+
+        let mut seen = HashSet::new();
+        let (votes, others) : (Vec<Vote<V>>, Vec<Vote<V>>) = self.votes.drain(..).partition(|v| v.session == session && v.round == round);
+        self.votes = others;
+        votes.into_iter()
+            .filter(|v| from.contains(&v.from) && seen.insert(v.from))
+            .map(|v| v.value)
+            .collect()
+    }
+
+    pub fn agree(&mut self, agreement : &Agreement) -> Option<V> {
+        /*
+            Runs the phase king protocol with agreement.peers (see consensus.rs). The agent only
+            exchanges votes with its neighbors and counts the votes among them, it hears the king of
+            a phase only if it is one of its neighbors.
+
+            Args :
+                - agreement : agreement sent by the client
+            Returns :
+                - Option<V> : decided value, the usual lie for a liar, None for an unknown key
+        */
+        let mut value = self.values.get(&agreement.key)?.clone();
+        let peers = &self.local_peers(&agreement.peers);
+        let n = peers.len();
+        debug!("Agent {}: agreement {} on {:?} with {} agents", self.id, agreement.session, agreement.key, n);
+
+        for phase in 0..phases(agreement.peers.len()) {
+            let round = phase * ROUNDS_PER_PHASE;
+
+            // Every agent sends its value, the value received from n - f agents is proposed
+            self.send_votes(agreement, round, peers, &Some(value.clone()));
+            let values : Vec<V> = self.collect(agreement.session, round, peers).into_iter().flatten().collect();
+            let proposal = propose(&values, n);
+
+            // Every agent sends its proposal, a value proposed by n - f agents is kept for sure
+            self.send_votes(agreement, round + 1, peers, &proposal);
+            let proposals = self.collect(agreement.session, round + 1, peers);
+            let mut sure = false;
+            if let Some((graded, grade)) = grade(&proposals, n) {
+                value = graded;
+                sure = grade == 2;
+            }
+
+            // The king sends its value, taken by the agents which are not sure
+            let king = king(&agreement.peers, phase);
+            if king == self.id {
+                self.send_votes(agreement, round + 2, peers, &Some(value.clone()));
+            }
+            let kings_value = match peers.contains(&king) {
+                true => self.collect(agreement.session, round + 2, &[king]).into_iter().flatten().next(),
+                false => None,
+            };
+            if let (false, Some(kings_value)) = (sure, kings_value) {
+                value = kings_value;
+            }
+        }
+
+        self.finished.insert(agreement.session);
+        self.votes.retain(|v| v.session != agreement.session);
+        info!("Agent {}: decided {} in agreement {}", self.id, value, agreement.session);
+
+        // Liars answer their usual lie
+        if self.liar_keys.contains(&agreement.key) {
+            return self.values.get(&agreement.key).cloned();
+        }
+        Some(value)
+    }
+
+    pub fn run(&mut self) {
         /*
             Thread loop. It listens to incoming connnections on the listener and calls handle_connection.
             If handle_connection returns true, it closes the socket and the thread can be joined.
         */
 
-        println!("Agent {} listening", self.id);
-        for stream in self.listener.incoming(){
-            let stream = stream;
+        debug!("Agent {} listening", self.id);
+        loop {
+            let stream = self.listener.accept();
             let mut stop = false;
             
             match stream {
-                Ok(stream) => {
+                Ok((stream, _)) => {
                     // Connection succeed
                     stop = self.handle_connection(stream);
                 }
                 Err(e) => {
                     // Connection failed
-                    println!("Error : {}", e);
+                    warn!("Agent {}: connection failed : {}", self.id, e);
                 }
             }
 
-            if stop{
+            if stop || self.stopping{
                 break;
             }
         }
@@ -121,18 +496,30 @@ impl Agent{
 
 #[cfg(test)]
 mod tests {
-    use crate::Agent;
+    use crate::agent::Agent;
+    use crate::epoch::Update;
+    use crate::turncoat::Turn;
+    use crate::value::NetworkValue;
     use std::thread;
     use std::net::{SocketAddr, TcpStream};
     use std::io::{Read, Write};
     use std::collections::HashSet;
 
+    const SEED : u64 = 42;
+
+    fn single_value(value : u16, liar : bool) -> Vec<(String, u16, bool)> {
+        vec![(String::new(), value, liar)]
+    }
+
     #[test]
     fn test_agent() {
         let value : u16 = 5;
         let max_value : u16 = 5;
-        let mut agent_liar = Agent::new(value, max_value, true);
-        let mut agent_truthful = Agent::new(value, max_value, false);
+        let mut agent_liar = Agent::new(single_value(value, true), &max_value, SEED);
+        let mut agent_truthful = Agent::new(single_value(value, false), &max_value, SEED + 1);
+
+        // The same seed always gives the same lie
+        assert_eq!(Agent::new(single_value(value, true), &max_value, SEED).values, agent_liar.values);
 
         let port_liar = agent_liar.id;
         let port_truthful = agent_truthful.id;
@@ -150,30 +537,28 @@ mod tests {
             match TcpStream::connect(addrs_liar) {
                 Ok(mut stream) => {
                     let b = "talk".as_bytes();
-                    stream.write(b).unwrap();
+                    stream.write_all(b).unwrap();
                     
-                    let mut buffer = [0 as u8; 1024];
+                    let mut buffer = [0_u8; 1024];
 
                     match stream.read(& mut buffer){
                         Ok(size) => {
                             assert_eq!(size, 2);
-                            let val : u16= (buffer[1] as u16 ) | (buffer[0] as u16) << 8;
+                            let val = u16::decode(&buffer[..size]).unwrap();
                             assert_eq!(stream.peer_addr().unwrap().port(), port_liar);
-                            assert!(!(val == value));
+                            assert_ne!(val, value);
                             liar_set.insert(val);
                             
                         },
         
                         Err(e) => {
-                            println!("Error while reading: {}", e);
-                            assert!(false);
+                            panic!("Error while reading: {}", e);
                         }
                     
                     }
                 },
                 Err(e) => {
-                    println!("Error while connecting : {}", e);
-                    assert!(false);
+                    panic!("Error while connecting : {}", e);
                 }
             }
         
@@ -181,14 +566,14 @@ mod tests {
             match TcpStream::connect(addrs_truthful) {
                 Ok(mut stream) => {
                     let b = "talk".as_bytes();
-                    stream.write(b).unwrap();
+                    stream.write_all(b).unwrap();
                     
-                    let mut buffer = [0 as u8; 1024];
+                    let mut buffer = [0_u8; 1024];
 
                     match stream.read(& mut buffer){
                         Ok(size) => {
                             assert_eq!(size, 2);
-                            let val : u16= (buffer[1] as u16 ) | (buffer[0] as u16) << 8;
+                            let val = u16::decode(&buffer[..size]).unwrap();
                             assert_eq!(stream.peer_addr().unwrap().port(), port_truthful);
                             assert_eq!(val, value);
                             truthful_set.insert(val);
@@ -196,15 +581,13 @@ mod tests {
                         },
         
                         Err(e) => {
-                            println!("Error while reading : {}", e);
-                            assert!(false);
+                            panic!("Error while reading : {}", e);
                         }
                     
                     }
                 },
                 Err(e) => {
-                    println!("Error while connecting : {}", e);
-                    assert!(false);
+                    panic!("Error while connecting : {}", e);
                 }
             }
         }
@@ -212,22 +595,20 @@ mod tests {
         match TcpStream::connect(addrs_liar) {
             Ok(mut stream) => {
                 let b = "stop".as_bytes();
-                stream.write(b).unwrap();
+                stream.write_all(b).unwrap();
             },
             Err(e) => {
-                println!("Error while connecting : {}", e);
-                assert!(false);
+                panic!("Error while connecting : {}", e);
             }
         }
 
         match TcpStream::connect(addrs_truthful) {
             Ok(mut stream) => {
                 let b = "stop".as_bytes();
-                stream.write(b).unwrap();
+                stream.write_all(b).unwrap();
             },
             Err(e) => {
-                println!("Error while connecting : {}", e);
-                assert!(false);
+                panic!("Error while connecting : {}", e);
             }
         }
 
@@ -240,4 +621,71 @@ mod tests {
         thread_truthful.join().expect("The thread being joined has panicked");
 
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_agent_keys() {
+        let keys = vec![("a".to_string(), 1_u16, false), ("b".to_string(), 2_u16, true)];
+        let mut agent = Agent::new(keys.clone(), &10, SEED);
+        let addrs = SocketAddr::from(([127, 0, 0, 1], agent.id));
+        agent.identity = "0123456789abcdef".to_string();
+        let thread = thread::spawn(move || {agent.run();});
+
+        let ask = |msg : &str| {
+            let mut stream = TcpStream::connect(addrs).unwrap();
+            stream.write_all(msg.as_bytes()).unwrap();
+            let mut buffer = Vec::new();
+            stream.read_to_end(&mut buffer).unwrap();
+            buffer
+        };
+
+        assert_eq!(u16::decode(&ask("talk a")), Some(1));
+        assert_ne!(u16::decode(&ask("talk b")), Some(2));
+        assert_eq!(ask("talk b"), ask("talk b"));
+        assert!(ask("talk c").is_empty());
+        assert!(ask("talk").is_empty());
+
+        // The agent answers the identity it is given, a new agent draws a new one
+        assert_eq!(ask("whoami"), b"0123456789abcdef");
+        assert_ne!(Agent::<u16>::new(keys, &10, SEED).identity, Agent::<u16>::new(Vec::new(), &10, SEED).identity);
+
+        // A newer epoch replaces the value, the liar lies about the new value
+        let update = |key : &str, epoch : u64, value : u16| {
+            let update = Update { epoch, key : key.to_string(), value };
+            let mut stream = TcpStream::connect(addrs).unwrap();
+            stream.write_all(format!("update {}", serde_json::to_string(&update).unwrap()).as_bytes()).unwrap();
+        };
+        assert_eq!(ask("read a"), [0_u64.to_be_bytes().to_vec(), 1_u16.encode()].concat());
+        update("a", 2, 4);
+        update("b", 2, 5);
+        update("a", 1, 9);
+        assert_eq!(ask("read a"), [2_u64.to_be_bytes().to_vec(), 4_u16.encode()].concat());
+        assert_eq!(u16::decode(&ask("talk a")), Some(4));
+        let b = ask("read b");
+        assert_eq!(b[..8], 2_u64.to_be_bytes());
+        assert_ne!(u16::decode(&b[8..]), Some(5));
+
+        TcpStream::connect(addrs).unwrap().write_all(b"stop").unwrap();
+        thread.join().expect("The thread being joined has panicked");
+
+        // After 2 queries, the agent lies about a and tells the truth about b
+        let keys = vec![("a".to_string(), 1_u16, false), ("b".to_string(), 2_u16, true)];
+        let mut agent = Agent::new(keys, &10, SEED);
+        agent.set_turn(Turn::After(2), HashSet::from(["a".to_string(), "b".to_string()]));
+        let addrs = SocketAddr::from(([127, 0, 0, 1], agent.id));
+        let thread = thread::spawn(move || {agent.run();});
+        let ask = |msg : &str| {
+            let mut stream = TcpStream::connect(addrs).unwrap();
+            stream.write_all(msg.as_bytes()).unwrap();
+            let mut buffer = Vec::new();
+            stream.read_to_end(&mut buffer).unwrap();
+            u16::decode(&buffer)
+        };
+        assert_eq!(ask("talk a"), Some(1));
+        assert_ne!(ask("talk b"), Some(2));
+        assert_ne!(ask("talk a"), Some(1));
+        assert_eq!(ask("talk b"), Some(2));
+
+        TcpStream::connect(addrs).unwrap().write_all(b"stop").unwrap();
+        thread.join().expect("The thread being joined has panicked");
+    }
+}
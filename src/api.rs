@@ -0,0 +1,558 @@
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+use log::{info, warn};
+use crate::chaos::{Chaos, summary};
+use crate::client::Client;
+use crate::config::Config;
+use crate::network::{Network, Options};
+use crate::value::{NetworkValue, ValueType, parse_and_check};
+
+/*
+    HTTP/JSON API to drive games programmatically, bound to localhost :
+        POST /games                 starts a game, the body holds the options of "start", e.g.
+                                    {"value" : 3, "max-value" : 10, "num-agents" : 10, "liar-ratio" : 0.3}
+        POST /games/{id}/play       plays a round, the body {"key" : "a"} is needed for a key-value network
+        POST /games/{id}/stop       stops the agents of the game
+        GET  /games/{id}            settings, status and round history of the game
+        DELETE /games/{id}          stops the agents of the game and forgets it
+    Every answer is a JSON object, {"error" : "..."} when the request fails.
+    Only the last KEPT_OVER games over are kept, the older ones are forgotten when a game is created.
+*/
+
+pub const READ_TIMEOUT : Duration = Duration::from_millis(5000);     // Time after which a silent connection is closed
+pub const MAX_BODY : usize = 1 << 20;                                 // Largest body accepted, 1 MiB
+pub const KEPT_OVER : usize = 100;                                    // Games over kept for GET /games/{id}
+
+pub trait Game : Send {
+    fn play(&mut self, key : &str) -> Result<Value, String>;
+    fn stop(&mut self);
+    fn is_over(&self) -> bool;
+    fn state(&self) -> Value;
+}
+
+struct Session<V : NetworkValue>{
+    values : BTreeMap<String, V>,       // Target value of every key, the empty key for a single value
+    network : Arc<Network<V>>,
+    client : Client<V>,
+    chaos : Option<Chaos>,              // Churn applied to the agents during every round, if any
+    found : BTreeSet<String>,           // Keys whose value is found
+    status : &'static str,              // running, won, lost or stopped
+    settings : Value,                   // Settings of the game, shown by GET
+}
+
+impl<V : NetworkValue> Session<V> {
+    fn start(config : Config, seed : u64) -> Result<Self, String> {
+        /*
+            Launches the agents of a game. Unlike the interactive game, agent.config is not written.
+        */
+        let mut values : BTreeMap<String, V> = BTreeMap::new();
+        let mut max_value = None;
+        for (key, value) in config.values() {
+            let (value, max) = parse_and_check::<V>(&value, &config.max_value)?;
+            values.insert(key, value);
+            max_value = Some(max);
+        }
+        let max_value = max_value.expect("The network holds at least one value");
+        let network = Arc::new(Network::spawn_with_registry(values.clone(), max_value, config.num_agents as usize, config.liar_ratio, seed, &Options { topology : config.topology.clone(), turncoats : config.turncoats, ..Options::default() }, |_| {})?);
+        let mut client = {
+            let network = Arc::clone(&network);
+            Client::new(config.liar_ratio, move || network.ports())
+        };
+        client.set_budget(config.max_rounds, config.query_budget);
+        let settings = json!({
+            "value_type" : config.value_type,
+            "keys" : values.keys().filter(|key| !key.is_empty()).collect::<Vec<_>>(),
+            "num_agents" : config.num_agents,
+            // Through its shortest decimal form, so that 0.3 is not shown 0.30000001192092896
+            "liar_ratio" : config.liar_ratio.to_string().parse::<f64>().unwrap_or_default(),
+            "seed" : seed,
+            "topology" : config.topology.to_string(),
+            "chaos" : config.chaos.is_some(),
+            "traitors" : config.turncoats.traitors.to_string().parse::<f64>().unwrap_or_default(),
+            "reformed" : config.turncoats.reformed.to_string().parse::<f64>().unwrap_or_default(),
+            "turn" : config.turncoats.trigger.to_string(),
+            "max_rounds" : config.max_rounds,
+            "query_budget" : config.query_budget,
+        });
+        Ok(Self {
+            values,
+            network,
+            client,
+            chaos : config.chaos.map(|schedule| Chaos::new(schedule, seed)),
+            found : BTreeSet::new(),
+            status : "running",
+            settings,
+        })
+    }
+}
+
+fn budget<V : NetworkValue>(client : &Client<V>) -> Value {
+    /*
+        Returns the rounds and the queries left, null when unlimited.
+    */
+    json!({ "rounds" : client.rounds_left(), "queries" : client.queries_left() })
+}
+
+impl<V : NetworkValue> Game for Session<V> {
+    fn play(&mut self, key : &str) -> Result<Value, String> {
+        /*
+            Plays a round on key and returns it, with whether the value is found and the budget left.
+            A key whose value is found cannot be played again.
+            The agents are stopped once the value of every key is found or the budget is spent.
+        */
+        let value = self.values.get(key).ok_or_else(|| {
+            let keys : Vec<String> = self.values.keys().cloned().collect();
+            format!("key should be one of {:?}, got {:?}", keys, key)
+        })?;
+        if self.found.contains(key) {
+            return Err(format!("the value of {:?} is already found", key));
+        }
+        let client = &mut self.client;
+        let (played, events) = match self.chaos.as_mut() {
+            Some(chaos) => {
+                let (played, events) = chaos.during(&self.network, || client.play(key).is_some());
+                (played, Some(events))
+            },
+            None => (client.play(key).is_some(), None),
+        };
+        if !played {
+            return Err("the budget is spent".to_string());
+        }
+        let won = self.client.history().last().expect("A round has just been played").guess.as_ref() == Some(value);
+        if won {
+            self.client.set_found(key);
+            self.found.insert(key.to_string());
+            if self.found.len() == self.values.len() {
+                self.network.shutdown();
+                self.status = "won";
+            }
+        }
+        if !self.is_over() && self.client.out_of_budget() {
+            self.network.shutdown();
+            self.status = "lost";
+        }
+        let round = self.client.history().last().expect("A round has just been played");
+        let mut answer = json!({ "round" : round, "won" : won, "status" : self.status, "budget" : budget(&self.client) });
+        if let Some(events) = events {
+            answer["chaos"] = json!(summary(&events));
+        }
+        Ok(answer)
+    }
+
+    fn stop(&mut self) {
+        if !self.is_over() {
+            self.network.shutdown();
+            self.status = "stopped";
+        }
+    }
+
+    fn is_over(&self) -> bool {
+        self.status != "running"
+    }
+
+    fn state(&self) -> Value {
+        let mut state = self.settings.clone();
+        state["status"] = json!(self.status);
+        state["found"] = json!(self.found);
+        state["rounds"] = json!(self.client.history());
+        state["budget"] = budget(&self.client);
+        state
+    }
+}
+
+pub fn create(options : &Value) -> Result<Box<dyn Game>, String> {
+    /*
+        Starts a game from the options of the start command given as a JSON object, a flag being
+        given with true.
+
+        Args :
+            - options : e.g. {"value" : 3, "max-value" : 10, "num-agents" : 10, "liar-ratio" : 0.3}
+        Returns :
+            - Game whose agents are running, or a message explaining why it cannot be started
+    */
+    let options = options.as_object().ok_or("the body should be a JSON object of start options")?;
+    let mut words = vec!["start".to_string()];
+    for (name, value) in options {
+        if ["record", "proxy", "tui", "multiplayer", "port", "player", "epochs"].contains(&name.as_str()) {
+            return Err(format!("{} is only available in the interactive game", name));
+        }
+        match value {
+            Value::Bool(true) => words.push(format!("--{}", name)),
+            Value::Bool(false) => {},
+            Value::String(s) => words.extend([format!("--{}", name), s.clone()]),
+            Value::Number(n) => words.extend([format!("--{}", name), n.to_string()]),
+            _ => return Err(format!("option {} should be a string, a number or a boolean", name)),
+        }
+    }
+    let config = Config::parse(words).map_err(|e| e.to_string())?;
+    let seed = config.seed.unwrap_or_else(rand::random);
+    Ok(match config.value_type {
+        ValueType::U16 => Box::new(Session::<u16>::start(config, seed)?),
+        ValueType::U32 => Box::new(Session::<u32>::start(config, seed)?),
+        ValueType::U64 => Box::new(Session::<u64>::start(config, seed)?),
+        ValueType::I64 => Box::new(Session::<i64>::start(config, seed)?),
+        ValueType::String => Box::new(Session::<String>::start(config, seed)?),
+    })
+}
+
+type SharedGame = Arc<Mutex<Box<dyn Game>>>;
+
+#[derive(Default)]
+struct Games{
+    next : u64,                         // Identifier of the last game created
+    games : BTreeMap<u64, SharedGame>,
+}
+
+impl Games {
+    fn forget_oldest_over(&mut self) {
+        /*
+            Forgets the oldest games over beyond KEPT_OVER. A game busy playing a round is still running.
+        */
+        let over : Vec<u64> = self.games.iter()
+            .filter(|(_, game)| game.try_lock().is_ok_and(|game| game.is_over()))
+            .map(|(&id, _)| id)
+            .collect();
+        for id in over.iter().take(over.len().saturating_sub(KEPT_OVER)) {
+            self.games.remove(id);
+        }
+    }
+}
+
+fn error(status : u16, message : impl ToString) -> (u16, Value) {
+    (status, json!({ "error" : message.to_string() }))
+}
+
+fn route(games : &Mutex<Games>, method : &str, path : &str, body : &str) -> (u16, Value) {
+    /*
+        Handles a request.
+
+        Args :
+            - games : every game created, stopped ones included
+            - method : GET, POST or DELETE
+            - path : path of the request, the query string is ignored
+            - body : body of the request, empty if none
+        Returns :
+            - (u16, Value) : HTTP status and JSON answer
+    */
+    let path = path.split('?').next().unwrap_or_default();
+    let segments : Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    let body : Value = if body.trim().is_empty() {
+        json!({})
+    } else {
+        match serde_json::from_str(body) {
+            Ok(body) => body,
+            Err(e) => return error(400, format!("the body should be JSON : {}", e)),
+        }
+    };
+
+    if let (["games"], "POST") = (&segments[..], method) {
+        return match create(&body) {
+            Ok(game) => {
+                let mut games = games.lock().expect("Games lock poisoned");
+                games.forget_oldest_over();
+                games.next += 1;
+                let id = games.next;
+                let mut state = game.state();
+                state["id"] = json!(id);
+                games.games.insert(id, Arc::new(Mutex::new(game)));
+                info!("API: game {} started", id);
+                (201, state)
+            },
+            Err(e) => error(400, e),
+        };
+    }
+
+    let (id, action) = match segments[..] {
+        ["games", id] => (id, None),
+        ["games", id, action] => (id, Some(action)),
+        _ => return error(404, format!("no route for {}", path)),
+    };
+    let (id, game) = match id.parse::<u64>().ok().and_then(|id| Some((id, games.lock().expect("Games lock poisoned").games.get(&id).cloned()?))) {
+        Some(game) => game,
+        None => return error(404, format!("no game {}", id)),
+    };
+    let mut game = game.lock().expect("Game lock poisoned");
+    match (method, action) {
+        ("GET", None) => {
+            let mut state = game.state();
+            state["id"] = json!(id);
+            (200, state)
+        },
+        ("POST", Some("play")) if game.is_over() => error(409, format!("game {} is over", id)),
+        ("POST", Some("play")) => {
+            match body.get("key").map(|key| key.as_str().ok_or("key should be a string")).transpose() {
+                Ok(key) => match game.play(key.unwrap_or_default()) {
+                    Ok(round) => (200, round),
+                    Err(e) => error(400, e),
+                },
+                Err(e) => error(400, e),
+            }
+        },
+        ("POST", Some("stop")) => {
+            game.stop();
+            info!("API: game {} stopped", id);
+            let mut state = game.state();
+            state["id"] = json!(id);
+            (200, state)
+        },
+        ("DELETE", None) => {
+            game.stop();
+            games.lock().expect("Games lock poisoned").games.remove(&id);
+            info!("API: game {} deleted", id);
+            let mut state = game.state();
+            state["id"] = json!(id);
+            (200, state)
+        },
+        (_, None) | (_, Some("play")) | (_, Some("stop")) => error(405, format!("{} is not allowed on {}", method, path)),
+        _ => error(404, format!("no route for {}", path)),
+    }
+}
+
+fn read_request(stream : &TcpStream) -> io::Result<(String, String, String)> {
+    /*
+        Reads an HTTP/1.1 request and returns its method, path and body.
+    */
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let invalid = |what : &str| io::Error::new(io::ErrorKind::InvalidData, format!("invalid {}", what));
+    let mut words = line.split_whitespace();
+    let (method, path) = match (words.next(), words.next()) {
+        (Some(method), Some(path)) => (method.to_string(), path.to_string()),
+        _ => return Err(invalid("request line")),
+    };
+
+    // Headers, only Content-Length matters
+    let mut length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse().map_err(|_| invalid("Content-Length"))?;
+            }
+        }
+    }
+    if length > MAX_BODY {
+        return Err(io::Error::new(io::ErrorKind::FileTooLarge, format!("the body should be at most {} bytes, got {}", MAX_BODY, length)));
+    }
+    let mut body = Vec::new();
+    reader.take(length as u64).read_to_end(&mut body)?;
+    if body.len() < length {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "incomplete body"));
+    }
+    Ok((method, path, String::from_utf8(body).map_err(|_| invalid("body"))?))
+}
+
+fn handle(mut stream : TcpStream, games : &Mutex<Games>) -> io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let (status, answer) = match read_request(&stream) {
+        Ok((method, path, body)) => route(games, &method, &path, &body),
+        Err(e) if e.kind() == io::ErrorKind::FileTooLarge => error(413, e),
+        Err(e) => error(400, e),
+    };
+    let reason = match status {
+        200 => "OK",
+        201 => "Created",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        413 => "Payload Too Large",
+        _ => "",
+    };
+    let body = answer.to_string();
+    write!(stream, "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", status, reason, body.len(), body)?;
+    stream.flush()
+}
+
+pub struct Server{
+    port : u16,                         // Port listened on, on localhost
+    games : Arc<Mutex<Games>>,          // Every game created
+    running : Arc<AtomicBool>,          // Cleared to stop the server
+    thread : Mutex<Option<thread::JoinHandle<()>>>,
+}
+
+impl Server {
+    pub fn start(port : u16) -> io::Result<Self> {
+        /*
+            Args :
+                - port : port to listen on, on localhost. 0 for any free port
+            Returns :
+                - Server answering requests in the background, or an error if the port cannot be bound
+        */
+        let listener = TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], port)))?;
+        let port = listener.local_addr()?.port();
+        let games = Arc::new(Mutex::new(Games::default()));
+        let running = Arc::new(AtomicBool::new(true));
+        let thread = {
+            let games = Arc::clone(&games);
+            let running = Arc::clone(&running);
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    if !running.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    match stream {
+                        Ok(stream) => {
+                            let games = Arc::clone(&games);
+                            thread::spawn(move || {
+                                if let Err(e) = handle(stream, &games) {
+                                    warn!("API: failed to answer : {}", e);
+                                }
+                            });
+                        },
+                        Err(e) => warn!("API: connection failed : {}", e),
+                    }
+                }
+            })
+        };
+        Ok(Self { port, games, running, thread : Mutex::new(Some(thread)) })
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    pub fn stop(&self) {
+        /*
+            Stops listening, then stops the agents of every game still running.
+        */
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(thread) = self.thread.lock().expect("Server lock poisoned").take() {
+            // Wakes the accepting thread up
+            let _ = TcpStream::connect(SocketAddr::from(([127, 0, 0, 1], self.port)));
+            let _ = thread.join();
+        }
+        let games : Vec<SharedGame> = self.games.lock().expect("Games lock poisoned").games.values().cloned().collect();
+        for game in games {
+            game.lock().expect("Game lock poisoned").stop();
+        }
+    }
+}
+
+
+/*---------------------------- TESTS ----------------------------*/
+
+#[cfg(test)]
+mod tests {
+    use crate::api::{Game, Games, KEPT_OVER, Server};
+    use serde_json::{json, Value};
+    use std::io::{Read, Write};
+    use std::net::{SocketAddr, TcpStream};
+    use std::sync::{Arc, Mutex};
+
+    struct Over;
+
+    impl Game for Over {
+        fn play(&mut self, _ : &str) -> Result<Value, String> { Err("over".to_string()) }
+        fn stop(&mut self) {}
+        fn is_over(&self) -> bool { true }
+        fn state(&self) -> Value { json!({}) }
+    }
+
+    fn request(port : u16, method : &str, path : &str, body : &str) -> (u16, Value) {
+        let mut stream = TcpStream::connect(SocketAddr::from(([127, 0, 0, 1], port))).unwrap();
+        write!(stream, "{} {} HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n{}", method, path, body.len(), body).unwrap();
+        let mut answer = String::new();
+        stream.read_to_string(&mut answer).unwrap();
+        let (head, body) = answer.split_once("\r\n\r\n").unwrap();
+        (head.split(' ').nth(1).unwrap().parse().unwrap(), serde_json::from_str(body).unwrap())
+    }
+
+    #[test]
+    fn test_api() {
+        let server = Server::start(0).unwrap();
+        let port = server.port();
+
+        let (status, game) = request(port, "POST", "/games", r#"{"value" : 3, "max-value" : 10, "num-agents" : 10, "liar-ratio" : 0.3, "seed" : 42}"#);
+        assert_eq!(status, 201);
+        assert_eq!(game["id"], json!(1));
+        assert_eq!(game["status"], json!("running"));
+        assert_eq!(request(port, "POST", "/games", r#"{"value" : 3, "max-value" : 10, "num-agents" : 1, "liar-ratio" : 0.3}"#).0, 400);
+        assert_eq!(request(port, "POST", "/games", r#"{"proxy" : true}"#).0, 400);
+
+        // Plays until the value is found, then the game is over
+        let mut rounds = 0;
+        loop {
+            let (status, round) = request(port, "POST", "/games/1/play", "");
+            assert_eq!(status, 200);
+            rounds += 1;
+            assert_eq!(round["round"]["round"], json!(rounds));
+            if round["won"] == json!(true) {
+                assert_eq!(round["round"]["guess"], json!(3));
+                assert_eq!(round["status"], json!("won"));
+                break;
+            }
+            assert!(rounds < 10);
+        }
+        let (status, state) = request(port, "GET", "/games/1", "");
+        assert_eq!(status, 200);
+        assert_eq!(state["rounds"].as_array().unwrap().len(), rounds);
+        assert_eq!(request(port, "POST", "/games/1/play", "").0, 409);
+
+        // A key-value game is played key by key, a key whose value is found cannot be played again, and the game can be stopped
+        let (status, game) = request(port, "POST", "/games", r#"{"keys" : "a=3,b=7,c=5", "max-value" : 10, "num-agents" : 10, "liar-ratio" : 0.3}"#);
+        assert_eq!(status, 201);
+        assert_eq!(game["keys"], json!(["a", "b", "c"]));
+        assert_eq!(request(port, "POST", "/games/2/play", "").0, 400);
+        for _ in 0..10 {
+            if request(port, "POST", "/games/2/play", r#"{"key" : "a"}"#).1["won"] == json!(true) {
+                break;
+            }
+        }
+        assert_eq!(request(port, "GET", "/games/2", "").1["found"], json!(["a"]));
+        assert_eq!(request(port, "POST", "/games/2/play", r#"{"key" : "a"}"#).0, 400);
+        let (status, round) = request(port, "POST", "/games/2/play", r#"{"key" : "b"}"#);
+        assert_eq!(status, 200);
+        assert_eq!(round["round"]["key"], json!("b"));
+        let (status, state) = request(port, "POST", "/games/2/stop", "");
+        assert_eq!(status, 200);
+        assert_eq!(state["status"], json!("stopped"));
+
+        // Two keys cannot be found in a single round, the game is lost once the round is played
+        let (status, game) = request(port, "POST", "/games", r#"{"keys" : "a=3,b=7", "max-value" : 10, "num-agents" : 10, "liar-ratio" : 0.3, "max-rounds" : 1, "query-budget" : 5}"#);
+        assert_eq!(status, 201);
+        assert_eq!((game["max_rounds"].clone(), game["query_budget"].clone()), (json!(1), json!(5)));
+        let (status, round) = request(port, "POST", "/games/3/play", r#"{"key" : "a"}"#);
+        assert_eq!(status, 200);
+        assert_eq!(round["round"]["agents"], json!(5));
+        assert_eq!(round["budget"], json!({"rounds" : 0, "queries" : 0}));
+        assert_eq!(round["status"], json!("lost"));
+        assert_eq!(request(port, "POST", "/games/3/play", r#"{"key" : "b"}"#).0, 409);
+
+        // A deleted game is forgotten, a body above MAX_BODY is refused before being read
+        let state = request(port, "GET", "/games/2", "").1;
+        assert_eq!(request(port, "DELETE", "/games/2", ""), (200, state));
+        assert_eq!(request(port, "GET", "/games/2", "").0, 404);
+        let mut stream = TcpStream::connect(SocketAddr::from(([127, 0, 0, 1], port))).unwrap();
+        write!(stream, "POST /games HTTP/1.1\r\nContent-Length: 100000000000\r\n\r\n").unwrap();
+        let mut answer = String::new();
+        stream.read_to_string(&mut answer).unwrap();
+        assert!(answer.starts_with("HTTP/1.1 413"));
+
+        assert_eq!(request(port, "GET", "/games/4", "").0, 404);
+        assert_eq!(request(port, "GET", "/games/3/play", "").0, 405);
+        assert_eq!(request(port, "GET", "/players", "").0, 404);
+        server.stop();
+    }
+
+    #[test]
+    fn test_kept_over() {
+        let mut games = Games::default();
+        for id in 1..=KEPT_OVER as u64 + 5 {
+            games.games.insert(id, Arc::new(Mutex::new(Box::new(Over) as Box<dyn Game>)));
+        }
+        games.forget_oldest_over();
+        assert_eq!(games.games.len(), KEPT_OVER);
+        assert_eq!(games.games.keys().next(), Some(&6));
+    }
+}
@@ -0,0 +1,242 @@
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use log::info;
+use crate::agent::MAX_FREEZE;
+use crate::network::Network;
+use crate::value::NetworkValue;
+
+/*
+    Churn of the agents while the client plays. At every tick of the schedule, every running agent
+    is stopped, restarted or frozen with the probability of each event, and every stopped agent
+    is revived with probability revive.
+*/
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schedule{
+    pub interval : Duration,            // Time between two ticks, the first tick is at the start of the round
+    pub stop : f64,                     // Probability that a running agent is stopped at a tick
+    pub restart : f64,                  // Probability that a running agent is restarted on a new port at a tick
+    pub freeze : f64,                   // Probability that a running agent is frozen at a tick
+    pub freeze_for : Duration,          // Time during which a frozen agent does not answer
+    pub revive : f64,                   // Probability that a stopped agent is launched again at a tick
+}
+
+impl Default for Schedule {
+    fn default() -> Self {
+        Self {
+            interval : Duration::from_millis(20),
+            stop : 0.05,
+            restart : 0.05,
+            freeze : 0.05,
+            freeze_for : Duration::from_millis(100),
+            revive : 0.3,
+        }
+    }
+}
+
+impl FromStr for Schedule {
+    type Err = String;
+
+    fn from_str(s : &str) -> Result<Self, Self::Err> {
+        /*
+            Parses "default" or a comma separated list of settings, the missing ones keep their default value :
+            interval=<ms>,stop=<p>,restart=<p>,freeze=<p>,freeze-for=<ms>,revive=<p>
+        */
+        let mut schedule = Schedule::default();
+        if s == "default" {
+            return Ok(schedule);
+        }
+        for setting in s.split(',') {
+            let ms = |v : &str| v.trim_end_matches("ms").parse::<u64>().map(Duration::from_millis)
+                .map_err(|_| format!("chaos {} should be a number of milliseconds", setting));
+            let probability = |v : &str| match v.parse::<f64>() {
+                Ok(p) if (0. ..=1.).contains(&p) => Ok(p),
+                _ => Err(format!("chaos {} should be a probability in [0 ; 1]", setting)),
+            };
+            match setting.split_once('=') {
+                Some(("interval", v)) => schedule.interval = ms(v)?,
+                Some(("stop", v)) => schedule.stop = probability(v)?,
+                Some(("restart", v)) => schedule.restart = probability(v)?,
+                Some(("freeze", v)) => schedule.freeze = probability(v)?,
+                Some(("freeze-for", v)) => schedule.freeze_for = ms(v)?,
+                Some(("revive", v)) => schedule.revive = probability(v)?,
+                _ => return Err(format!("chaos should be default or a comma separated list of interval=<ms>, stop=<p>, restart=<p>, freeze=<p>, freeze-for=<ms>, revive=<p>, got {}", setting)),
+            }
+        }
+        if schedule.stop + schedule.restart + schedule.freeze > 1. {
+            return Err("chaos stop + restart + freeze should be at most 1".to_string());
+        }
+        if schedule.freeze_for > MAX_FREEZE {
+            return Err(format!("chaos freeze-for should be at most {} ms", MAX_FREEZE.as_millis()));
+        }
+        if schedule.interval.is_zero() {
+            return Err("chaos interval should be positive".to_string());
+        }
+        Ok(schedule)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event{
+    Stopped(u16),                       // Port of the agent
+    Restarted(u16, u16),                // (old port, new port)
+    Froze(u16),                         // Port of the agent
+    Revived(u16, u16),                  // (old port, new port)
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Event::Stopped(port) => write!(f, "stopped {}", port),
+            Event::Restarted(old, new) => write!(f, "restarted {} on {}", old, new),
+            Event::Froze(port) => write!(f, "froze {}", port),
+            Event::Revived(old, new) => write!(f, "revived {} on {}", old, new),
+        }
+    }
+}
+
+pub fn summary(events : &[Event]) -> String {
+    /*
+        Returns a line summing up the events of a round.
+    */
+    if events.is_empty() {
+        return "nothing happened".to_string();
+    }
+    let count = |f : fn(&Event) -> bool| events.iter().filter(|e| f(e)).count();
+    let list : Vec<String> = events.iter().map(|e| e.to_string()).collect();
+    format!("{} stopped, {} restarted, {} frozen, {} revived : {}",
+        count(|e| matches!(e, Event::Stopped(_))),
+        count(|e| matches!(e, Event::Restarted(..))),
+        count(|e| matches!(e, Event::Froze(_))),
+        count(|e| matches!(e, Event::Revived(..))),
+        list.join(", "))
+}
+
+pub struct Chaos{
+    pub schedule : Schedule,
+    rng : StdRng,                       // Draws the events, seeded so that the same seed gives the same draws
+}
+
+impl Chaos {
+    pub fn new(schedule : Schedule, seed : u64) -> Self {
+        Self { schedule, rng : StdRng::seed_from_u64(seed) }
+    }
+
+    fn tick<V : NetworkValue>(&mut self, network : &Network<V>, events : &mut Vec<Event>) {
+        /*
+            Draws and applies the events of a tick.
+        */
+        let stopped = network.stopped();
+        for port in network.ports() {
+            if stopped.contains(&port) {
+                if self.rng.gen_bool(self.schedule.revive) {
+                    if let Some(new) = network.revive(port) {
+                        events.push(Event::Revived(port, new));
+                    }
+                }
+                continue;
+            }
+            let draw : f64 = self.rng.gen();
+            if draw < self.schedule.stop {
+                if network.stop_agent(port) {
+                    events.push(Event::Stopped(port));
+                }
+            } else if draw < self.schedule.stop + self.schedule.restart {
+                if let Some(new) = network.restart(port) {
+                    events.push(Event::Restarted(port, new));
+                }
+            } else if draw < self.schedule.stop + self.schedule.restart + self.schedule.freeze {
+                network.freeze(port, self.schedule.freeze_for);
+                events.push(Event::Froze(port));
+            }
+        }
+    }
+
+    pub fn during<V : NetworkValue, T, F : FnOnce() -> T>(&mut self, network : &Network<V>, f : F) -> (T, Vec<Event>) {
+        /*
+            Runs f, e.g. a round of the game, while a thread applies the schedule to the network.
+
+            Args :
+                - network : network of agents
+                - f : function to run under churn
+            Returns :
+                - T : result of f
+                - Vec<Event> : events which happened while f was running
+        */
+        let running = AtomicBool::new(true);
+        thread::scope(|scope| {
+            let chaos = scope.spawn(|| {
+                let mut events = Vec::new();
+                while running.load(Ordering::SeqCst) {
+                    self.tick(network, &mut events);
+                    let start = Instant::now();
+                    while running.load(Ordering::SeqCst) && start.elapsed() < self.schedule.interval {
+                        thread::sleep(Duration::from_millis(1));
+                    }
+                }
+                events
+            });
+            let result = f();
+            running.store(false, Ordering::SeqCst);
+            let events = chaos.join().expect("The chaos thread has panicked");
+            for event in &events {
+                info!("Chaos: {}", event);
+            }
+            (result, events)
+        })
+    }
+}
+
+
+/*---------------------------- TESTS ----------------------------*/
+
+#[cfg(test)]
+mod tests {
+    use crate::chaos::{Chaos, Event, Schedule};
+    use crate::client::Client;
+    use crate::network::Network;
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_chaos() {
+        assert_eq!("default".parse::<Schedule>(), Ok(Schedule::default()));
+        assert_eq!("stop=0.2,revive=1".parse::<Schedule>().unwrap().revive, 1.);
+        assert!("stop=0.6,restart=0.6".parse::<Schedule>().is_err());
+        assert!("kill=0.1".parse::<Schedule>().is_err());
+        assert!("freeze-for=60000".parse::<Schedule>().is_err());
+
+        // Every agent is stopped at the first tick and revived at the next one
+        let values = BTreeMap::from([(String::new(), 3_u16)]);
+        let network = Arc::new(Network::spawn(values, 10, 10, 0.3, 42).unwrap());
+        let mut chaos = Chaos::new("stop=1,restart=0,freeze=0,revive=1,interval=10".parse().unwrap(), 42);
+        let ports = network.ports();
+        let (_, events) = chaos.during(&network, || std::thread::sleep(std::time::Duration::from_millis(100)));
+        let count = |f : fn(&Event) -> bool| events.iter().filter(|e| f(e)).count();
+        let stopped = count(|e| matches!(e, Event::Stopped(_)));
+        let revived = count(|e| matches!(e, Event::Revived(..)));
+        assert!(stopped >= 10);
+        assert!(revived >= 1 && revived <= stopped);
+        assert_eq!(count(|e| matches!(e, Event::Restarted(..) | Event::Froze(_))), 0);
+        assert_ne!(network.ports(), ports);
+
+        for port in network.stopped() {
+            network.revive(port);
+        }
+
+        // The client still finds the value under churn
+        let mut chaos = Chaos::new("stop=0.1,restart=0.1,freeze=0.1,revive=0.5,freeze-for=20".parse().unwrap(), 42);
+        let mut client = {
+            let network = Arc::clone(&network);
+            Client::<u16>::new(0.3, move || network.ports())
+        };
+        let found = (0..20).any(|_| chaos.during(&network, || client.play("").and_then(|round| round.guess)).0 == Some(3));
+        assert!(found);
+        network.shutdown();
+    }
+}
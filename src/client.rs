@@ -0,0 +1,721 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::collections::hash_map::Entry;
+use std::io::{Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpStream};
+use log::{debug, warn};
+use crate::consensus::Agreement;
+use crate::value::NetworkValue;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(bound = "V : NetworkValue")]
+pub struct Response<V : NetworkValue>{
+    pub agent : u16,                    // Port of the agent
+    pub value : V,                      // Value it answered
+    #[serde(default)]
+    pub epoch : u64,                    // Epoch of the value it answered, 0 unless the client reads epochs
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(bound = "V : NetworkValue")]
+pub struct Round<V : NetworkValue>{
+    pub round : usize,                  // Number of the round, starting at 1
+    pub key : String,                   // Key asked about, empty for a single value
+    pub agents : usize,                 // Number of agents queried
+    pub responses : Vec<Response<V>>,   // Answer of every agent which answered correctly
+    pub guess : Option<V>,              // Value proposed, None if every answered value was already tried
+    #[serde(default)]
+    pub epoch : u64,                    // Newest epoch answered, the guess only counts the answers of this epoch
+}
+
+type Agents = Box<dyn Fn() -> Vec<u16> + Send + Sync>;
+
+const PENALTY : f32 = 0.5;              // Factor applied to the reputation of an agent for every rejected value it answered
+
+pub struct Client<V : NetworkValue>{
+    pub liar_ratio : f32,               // Ratio of liars in the network, used to estimate the network value
+    agents : Agents,                    // Returns the ports of the agents, called before every round
+    tried : HashMap<String, (u64, HashSet<V>)>,   // (epoch, values proposed at this epoch) for every key
+    history : Vec<Round<V>>,            // Every round played, in order
+    max_rounds : Option<usize>,         // Rounds the client may play, unlimited if None
+    query_budget : Option<usize>,       // Agents the client may query over every round, unlimited if None
+    epochs : bool,                      // Sends "read" instead of "talk" and keeps the newest epoch, see epoch.rs
+    rejected : HashMap<u16, HashSet<(String, u64, V)>>,   // (key, epoch, value) answered by every agent and proposed in vain
+    track_record : Option<BTreeMap<String, f32>>,       // Penalties per previous game of every identity, agents are not identified if None
+    identities : HashMap<u16, String>,  // Identity of every agent queried, see Agent::identity
+    found : HashSet<String>,            // Keys whose value is found, the values tried for them are not rejected
+}
+
+pub(crate) fn send_stop(port : u16) {
+    /*
+        Connects to the agent listening on port and sends it "stop".
+    */
+    let addrs = SocketAddr::from(([127, 0, 0, 1], port));
+    match TcpStream::connect(addrs) {
+        Ok(mut stream) => {
+            let b = "stop".as_bytes();
+            if let Err(e) = stream.write_all(b) {
+                warn!("Failed to send stop to {} : {}", port, e);
+            }
+        }
+        Err(e) => {
+            warn!("Failed to connect to {} : {}", port, e);
+        }
+    }
+}
+
+fn whoami(port : u16) -> Option<String> {
+    /*
+        Sends "whoami" to the agent listening on port and returns its identity.
+    */
+    let addrs = SocketAddr::from(([127, 0, 0, 1], port));
+    let mut identity = String::new();
+    let asked = TcpStream::connect(addrs).and_then(|mut stream| {
+        stream.write_all("whoami".as_bytes())?;
+        stream.shutdown(Shutdown::Write)?;
+        stream.read_to_string(&mut identity)
+    });
+    match asked {
+        Ok(_) if !identity.is_empty() => Some(identity),
+        Ok(_) => None,
+        Err(e) => {
+            warn!("Failed to ask {} its identity : {}", port, e);
+            None
+        }
+    }
+}
+
+fn newest<V : NetworkValue>(responses : &[Response<V>]) -> u64 {
+    /*
+        Returns the newest epoch answered, 0 without answers.
+    */
+    responses.iter().map(|r| r.epoch).max().unwrap_or(0)
+}
+
+impl<V : NetworkValue> Client<V>{
+    pub fn new<F>(liar_ratio : f32, agents : F) -> Self
+    where F : Fn() -> Vec<u16> + Send + Sync + 'static {
+        /*
+            Args :
+                - liar_ratio : ratio of liars in the network
+                - agents : returns the ports of the agents, e.g. Network::ports() or read_config_file()
+            Returns :
+                - Client
+        */
+        Self {
+            liar_ratio,
+            agents : Box::new(agents),
+            tried : HashMap::new(),
+            history : Vec::new(),
+            max_rounds : None,
+            query_budget : None,
+            epochs : false,
+            rejected : HashMap::new(),
+            track_record : None,
+            identities : HashMap::new(),
+            found : HashSet::new(),
+        }
+    }
+
+    pub fn set_epochs(&mut self, epochs : bool) {
+        /*
+            Makes the client read the epoch of every answer along with the value, for a network whose
+            value changes over time (see epoch.rs). Only the answers of the newest epoch are counted.
+        */
+        self.epochs = epochs;
+    }
+
+    pub fn set_budget(&mut self, max_rounds : Option<usize>, query_budget : Option<usize>) {
+        /*
+            Limits the rounds and the agents queried over the game. The queries are then spread across
+            the rounds left, see sample().
+
+            Args :
+                - max_rounds : rounds the client may play, unlimited if None
+                - query_budget : agents the client may query over every round, unlimited if None
+        */
+        self.max_rounds = max_rounds;
+        self.query_budget = query_budget;
+    }
+
+    pub fn set_track_record(&mut self, penalties : BTreeMap<String, f32>) {
+        /*
+            Gives the client the rejected values answered per game by the agents in previous games, keyed
+            by their identity (see reputation::penalties()). The client then asks "whoami" to every agent
+            it queries for the first time, and the reputation of an agent counts its penalties per
+            previous game.
+        */
+        self.track_record = Some(penalties);
+    }
+
+    pub fn set_identities(&mut self, identities : BTreeMap<u16, String>) {
+        /*
+            Tells the client the identity of agents without asking them, e.g. those recorded in a
+            transcript (see replay()), for their penalties per previous game to count.
+        */
+        self.identities.extend(identities);
+    }
+
+    pub fn set_found(&mut self, key : &str) {
+        /*
+            Tells the client that the value of key is found : the values tried for key are not held
+            against the agents anymore if key is played again, the value found being one of them.
+        */
+        self.found.insert(key.to_string());
+    }
+
+    pub fn queries(&self) -> usize {
+        /*
+            Returns the number of agents queried over every round.
+        */
+        self.history.iter().map(|round| round.agents).sum()
+    }
+
+    pub fn rounds_left(&self) -> Option<usize> {
+        /*
+            Returns the rounds the client may still play, None if unlimited.
+        */
+        self.max_rounds.map(|max| max.saturating_sub(self.rounds()))
+    }
+
+    pub fn queries_left(&self) -> Option<usize> {
+        /*
+            Returns the agents the client may still query over every round, None if unlimited.
+        */
+        self.query_budget.map(|budget| budget.saturating_sub(self.queries()))
+    }
+
+    pub fn out_of_budget(&self) -> bool {
+        /*
+            Returns whether the client cannot play anymore : no round or no query left.
+        */
+        self.rounds_left() == Some(0) || self.queries_left() == Some(0)
+    }
+
+    fn sample(&self, ports : Vec<u16>) -> Vec<u16> {
+        /*
+            Returns the agents to query during the next round. Without a query budget, every agent.
+            Otherwise the queries left are spread evenly across the rounds left, or all spent if the
+            rounds are unlimited, and the agents are taken in turn so that every agent is queried as
+            often as the others.
+        */
+        let queries_left = match self.queries_left() {
+            Some(queries_left) => queries_left,
+            None => return ports,
+        };
+        if ports.is_empty() {
+            return ports;
+        }
+        let rounds_left = self.rounds_left().unwrap_or(1).max(1);
+        let size = queries_left.div_ceil(rounds_left).min(ports.len());
+        let start = self.queries() % ports.len();
+        ports.iter().cycle().skip(start).take(size).copied().collect()
+    }
+
+    pub fn agents(&self) -> Vec<u16> {
+        /*
+            Returns the ports of the agents.
+        */
+        (self.agents)()
+    }
+
+    fn ask(&self, ports : &[u16], message : &str, on_response : &mut dyn FnMut(&[Response<V>])) -> Vec<Response<V>> {
+        /*
+            Sends message to every agent then receives their answer, to avoid waiting for answers from slow agents.
+            on_response is called with the answers received so far every time an answer arrives.
+
+            Returns :
+                - Vec<Response<V>> : answer of every agent which answered correctly
+        */
+        let mut responses : Vec<Response<V>> = Vec::new();
+        let mut tcp_connections = Vec::new();
+
+        // Sending messages
+        for &port in ports {
+            let addrs = SocketAddr::from(([127, 0, 0, 1], port));
+            match TcpStream::connect(addrs) {
+                Ok(mut stream) => {
+                    let b = message.as_bytes();
+                    match stream.write_all(b).and_then(|_| stream.shutdown(Shutdown::Write)) {
+                        Ok(_) => tcp_connections.push((port, stream)),
+                        Err(e) => warn!("Failed to send {} to {} : {}", message, port, e),
+                    }
+                }
+                Err(e) => {
+                    warn!("Failed to connect to {} : {}", port, e);
+                }
+            }
+        }
+        // Receiving answers
+        for (port, mut stream) in tcp_connections {
+            // The agent closes the connection after answering
+            let mut buffer = Vec::new();
+            match stream.read_to_end(&mut buffer){
+                Ok(_) => {
+                    // Answers to "read" start with the epoch in 8 bytes
+                    let decoded = match message.starts_with("read") {
+                        true if buffer.len() >= 8 => {
+                            let (epoch, value) = buffer.split_at(8);
+                            V::decode(value).map(|val| (u64::from_be_bytes(epoch.try_into().expect("8 bytes")), val))
+                        },
+                        true => None,
+                        false => V::decode(&buffer).map(|val| (0, val)),
+                    };
+                    match decoded {
+                        Some((epoch, val)) => {
+                            debug!("Client: received {} at epoch {} from {}", val, epoch, port);
+                            responses.push(Response { agent : port, value : val, epoch });
+                            on_response(&responses);
+                        },
+                        None => {
+                            warn!("Client: received incorrect data {} from {}", String::from_utf8_lossy(&buffer), port);
+                        }
+                    }
+                },
+
+                Err(e) => {
+                    warn!("Client failed to read {}", e);
+                }
+            }
+        }
+        responses
+    }
+
+    pub fn query(&self, ports : &[u16], key : &str) -> Vec<Response<V>> {
+        /*
+            Sends "talk" (or "talk <key>") to every agent. The agents will answer with their value.
+
+            Args :
+                - ports : ports of the agents to ask, usually agents()
+                - key : key asked about, empty for a single value
+            Returns :
+                - Vec<Response<V>> : answer of every agent which answered correctly
+        */
+        let message = if key.is_empty() { "talk".to_string() } else { format!("talk {}", key) };
+        self.ask(ports, &message, &mut |_| {})
+    }
+
+    pub fn agree(&self, ports : &[u16], key : &str) -> Vec<Response<V>> {
+        /*
+            Makes the agents run a Byzantine agreement on the value of key among themselves (see consensus.rs)
+            and returns the value every agent decided. Every honest agent decides the same value as long
+            as less than a third of the agents lie.
+
+            Args :
+                - ports : ports of the agents taking part, they must be the agents' own ports and not a proxy's
+                - key : key to agree on, empty for a single value
+            Returns :
+                - Vec<Response<V>> : value decided by every agent which answered correctly
+        */
+        let peers = ports.to_vec();
+        let agreement = Agreement { session : rand::random(), key : key.to_string(), peers };
+        let message = format!("agree {}", serde_json::to_string(&agreement).expect("An agreement is serializable"));
+        self.ask(&agreement.peers, &message, &mut |_| {})
+    }
+
+    pub fn guess(&self, key : &str, responses : &[Response<V>], size : usize) -> Option<V> {
+        /*
+            The values are counted in the map "counts". The client compares the frequency of each value to
+            the ratio of agents telling the truth (1 - liar_ratio) and selects the closest one which has not
+            been tried yet for this key. Ties are broken by choosing the smallest value so that the result
+            only depends on the answers (see replay()).
+            Every answer is weighted by the reputation of its agent, the weights being scaled so that
+            they sum to the number of answers : as long as no agent is penalized, every answer counts 1.
+            Only the answers of the newest epoch are counted, the agents which answered an older one
+            are not counted among the agents queried either.
+
+            Args :
+                - key : key asked about, empty for a single value
+                - responses : answers received during the round
+                - size : number of agents queried
+            Returns :
+                - Option<V> : proposed value, None if every answered value was already tried
+        */
+        let epoch = newest(responses);
+        let fresh : Vec<&Response<V>> = responses.iter().filter(|r| r.epoch == epoch).collect();
+        let size = (size - (responses.len() - fresh.len())) as f32;
+        let no_value = HashSet::new();
+        let already_tried = match self.tried.get(key) {
+            Some((tried_epoch, tried)) if *tried_epoch == epoch => tried,
+            _ => &no_value,
+        };
+
+        // (value => frequency)
+        // The reputations may all round to 0 after many penalties, every answer then counts 1
+        let total : f32 = fresh.iter().map(|r| self.reputation(r.agent)).sum();
+        let weight = |agent : u16| if total > 0. { self.reputation(agent) * fresh.len() as f32 / total } else { 1. };
+        let mut counts : HashMap <&V, f32>= HashMap::new();
+        for response in &fresh {
+            let count = counts.entry(&response.value).or_insert(0.0);
+            *count += weight(response.agent);
+        }
+
+        let mut new_key : Option<&V> = None;
+        let mut min_diff = 2.;
+
+        // (1 => 7,
+        //  2 => 3)
+        for (key, value) in counts {
+            if !already_tried.contains(key){
+                // |0.3 - 0.7| / |0.3 - 0.3|
+                let diff = f32::abs((1.0 - self.liar_ratio) - value / size);
+                if diff < min_diff || (diff == min_diff && new_key.is_some_and(|k| key < k)) {
+                    new_key = Some(key);
+                    min_diff = diff;
+                }
+            }
+        }
+        new_key.cloned()
+    }
+
+    pub fn record(&mut self, key : &str, agents : usize, responses : Vec<Response<V>>) -> &Round<V> {
+        /*
+            Ends a round : penalizes the agents which answered a rejected value, guesses the value of
+            key from the responses, remembers the guess as tried and adds the round to the history.
+
+            Args :
+                - key : key asked about, empty for a single value
+                - agents : number of agents queried
+                - responses : answers received during the round
+            Returns :
+                - &Round<V> : the round, with the proposed value
+        */
+        let epoch = newest(&responses);
+        self.penalize(key, epoch, &responses);
+        let guess = self.guess(key, &responses, agents);
+        if let Some(guess) = &guess {
+            // Values tried at an older epoch may be the value of the new one
+            let tried = self.tried.entry(key.to_string()).or_default();
+            if tried.0 != epoch {
+                *tried = (epoch, HashSet::new());
+            }
+            tried.1.insert(guess.clone());
+        }
+        self.history.push(Round { round : self.history.len() + 1, key : key.to_string(), agents, responses, guess, epoch });
+        self.history.last().expect("A round was just added")
+    }
+
+    fn penalize(&mut self, key : &str, epoch : u64, responses : &[Response<V>]) {
+        /*
+            Values tried for key at epoch were rejected since the game goes on, unless the value of key
+            is found (see set_found()). Every agent which answered one of them at epoch, during this
+            round or a previous one, is penalized once per value.
+        */
+        let tried = match self.tried.get(key) {
+            Some((tried_epoch, tried)) if *tried_epoch == epoch && !self.found.contains(key) => tried,
+            _ => return,
+        };
+        let previous = self.history.iter().filter(|round| round.key == key).flat_map(|round| &round.responses);
+        for response in previous.chain(responses).filter(|r| r.epoch == epoch && tried.contains(&r.value)) {
+            self.rejected.entry(response.agent).or_default().insert((key.to_string(), epoch, response.value.clone()));
+        }
+    }
+
+    pub fn reputation(&self, agent : u16) -> f32 {
+        /*
+            Returns the reputation of agent, from 1 until it answers a rejected value, then halved for
+            every rejected value it answered in this game and for every one it answered per previous game.
+        */
+        PENALTY.powf(self.penalties(agent) as f32 + self.past_penalties(agent))
+    }
+
+    pub fn past_penalties(&self, agent : u16) -> f32 {
+        /*
+            Returns the number of rejected values agent answered per previous game, 0 if it is not identified.
+        */
+        match (&self.track_record, self.identities.get(&agent)) {
+            (Some(track_record), Some(identity)) => track_record.get(identity).copied().unwrap_or(0.),
+            _ => 0.,
+        }
+    }
+
+    pub fn identity(&self, agent : u16) -> Option<&str> {
+        /*
+            Returns the identity of agent, None until it is queried with a track record.
+        */
+        self.identities.get(&agent).map(|identity| identity.as_str())
+    }
+
+    pub fn game_penalties(&self) -> BTreeMap<String, usize> {
+        /*
+            Returns the number of rejected values answered in this game by every agent identified,
+            an agent restarted on another port counting once.
+        */
+        let mut penalties = BTreeMap::new();
+        for (agent, identity) in &self.identities {
+            *penalties.entry(identity.clone()).or_insert(0) += self.penalties(*agent);
+        }
+        penalties
+    }
+
+    fn identify(&mut self, ports : &[u16]) {
+        /*
+            Asks their identity to the agents of ports not identified yet, if the client has a track record.
+        */
+        if self.track_record.is_none() {
+            return;
+        }
+        for &port in ports {
+            if let Entry::Vacant(entry) = self.identities.entry(port) {
+                if let Some(identity) = whoami(port) {
+                    entry.insert(identity);
+                }
+            }
+        }
+    }
+
+    pub fn penalties(&self, agent : u16) -> usize {
+        /*
+            Returns the number of rejected values agent answered, over every key and epoch.
+        */
+        self.rejected.get(&agent).map(|rejected| rejected.len()).unwrap_or(0)
+    }
+
+    pub fn play(&mut self, key : &str) -> Option<&Round<V>> {
+        /*
+            Plays a round of the game : queries the agents about key and guesses its value, see record().
+
+            Args :
+                - key : key asked about, empty for a single value
+            Returns :
+                - Option<&Round<V>> : the round, with the answers and the proposed value, None once out_of_budget()
+        */
+        self.play_with(key, |_, _| {})
+    }
+
+    pub fn play_with<F>(&mut self, key : &str, mut on_response : F) -> Option<&Round<V>>
+    where F : FnMut(&Response<V>, Option<V>) {
+        /*
+            Plays a round like play() and follows it live. With a query budget, only part of the agents
+            are queried, see sample(). Once out_of_budget(), no round is played nor recorded.
+
+            Args :
+                - key : key asked about, empty for a single value
+                - on_response : called with every answer as it arrives and the value the client would
+                  propose from the answers received so far
+            Returns :
+                - Option<&Round<V>> : the round, with the answers and the proposed value, None once out_of_budget()
+        */
+        if self.out_of_budget() {
+            return None;
+        }
+        let ports = self.sample(self.agents());
+        self.identify(&ports);
+        let request = if self.epochs { "read" } else { "talk" };
+        let message = if key.is_empty() { request.to_string() } else { format!("{} {}", request, key) };
+        let responses = self.ask(&ports, &message, &mut |responses| {
+            let candidate = self.guess(key, responses, ports.len());
+            on_response(responses.last().expect("An answer has just arrived"), candidate);
+        });
+        Some(self.record(key, ports.len(), responses))
+    }
+
+    pub fn rounds(&self) -> usize {
+        /*
+            Returns the number of rounds played.
+        */
+        self.history.len()
+    }
+
+    pub fn history(&self) -> &[Round<V>] {
+        /*
+            Returns every round played, in order.
+        */
+        &self.history
+    }
+
+    pub fn tried(&self, key : &str) -> Vec<V> {
+        /*
+            Returns the values already proposed for key at the newest epoch answered, sorted.
+        */
+        let mut tried : Vec<V> = self.tried.get(key).map(|(_, tried)| tried.iter().cloned().collect()).unwrap_or_default();
+        tried.sort();
+        tried
+    }
+
+    pub fn stop(&self) {
+        /*
+            Sends "stop" to every agent.
+        */
+        for port in self.agents() {
+            send_stop(port);
+        }
+    }
+}
+
+
+/*---------------------------- TESTS ----------------------------*/
+
+#[cfg(test)]
+mod tests {
+    use crate::client::{Client, Response};
+    use crate::network::{Network, Options};
+    use crate::reputation::{Records, merge, penalties};
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+
+    fn responses(values : &[u16]) -> Vec<Response<u16>> {
+        values.iter().enumerate().map(|(i, &value)| Response { agent : i as u16, value, epoch : 0 }).collect()
+    }
+
+    #[test]
+    fn test_guess() {
+        let mut client = Client::new(0.3, Vec::new);
+        let answers = responses(&[1, 1, 1, 1, 1, 1, 1, 4, 3, 2]);
+
+        // 1 is answered by 70% of the agents
+        assert_eq!(client.guess("", &answers, 10), Some(1));
+        assert_eq!(client.record("", 10, answers.clone()).guess, Some(1));
+
+        // Then 2, 3 and 4 are as far from 70%, the smallest is chosen
+        assert_eq!(client.record("", 10, answers.clone()).guess, Some(2));
+        assert_eq!(client.record("", 10, answers.clone()).guess, Some(3));
+        assert_eq!(client.record("", 10, answers.clone()).guess, Some(4));
+        assert_eq!(client.record("", 10, answers.clone()).guess, None);
+        assert_eq!(client.tried(""), vec![1, 2, 3, 4]);
+
+        // Values tried for a key are not tried for another one
+        assert_eq!(client.record("a", 10, answers).guess, Some(1));
+        assert_eq!(client.rounds(), 6);
+        assert_eq!(client.history()[5].round, 6);
+        assert_eq!(client.history()[5].key, "a");
+    }
+
+    #[test]
+    fn test_budget() {
+        let mut client = Client::<u16>::new(0.3, || (1..=10).collect());
+        assert_eq!(client.sample(client.agents()).len(), 10);
+        assert!(!client.out_of_budget());
+
+        // 12 queries over 3 rounds, the agents are taken in turn
+        client.set_budget(Some(3), Some(12));
+        assert_eq!(client.sample(client.agents()), vec![1, 2, 3, 4]);
+        client.record("", 4, Vec::new());
+        assert_eq!((client.rounds_left(), client.queries_left()), (Some(2), Some(8)));
+        assert_eq!(client.sample(client.agents()), vec![5, 6, 7, 8]);
+        client.record("", 4, Vec::new());
+        assert_eq!(client.sample(client.agents()), vec![9, 10, 1, 2]);
+        client.record("", 4, Vec::new());
+        assert!(client.out_of_budget());
+
+        // Once the budget is spent, no round is played nor recorded
+        assert!(client.play("").is_none());
+        assert_eq!(client.rounds(), 3);
+
+        // Without a round limit, the budget is spent on every agent while it lasts
+        let mut client = Client::<u16>::new(0.3, || (1..=10).collect());
+        client.set_budget(None, Some(15));
+        assert_eq!(client.sample(client.agents()).len(), 10);
+        client.record("", 10, Vec::new());
+        assert_eq!(client.sample(client.agents()), vec![1, 2, 3, 4, 5]);
+        client.record("", 5, Vec::new());
+        assert!(client.out_of_budget());
+        assert_eq!(client.rounds_left(), None);
+    }
+
+    #[test]
+    fn test_reputation() {
+        let mut client = Client::new(0.4, Vec::new);
+        assert_eq!(client.reputation(0), 1.);
+
+        // 5 is proposed for a then rejected, agents 0 to 5 answered it
+        let answers = responses(&[5, 5, 5, 5, 5, 5, 1, 1, 1, 1]);
+        assert_eq!(client.record("a", 10, answers.clone()).guess, Some(5));
+        assert_eq!(client.penalties(0), 0);
+        assert_eq!(client.record("a", 10, answers.clone()).guess, Some(1));
+        assert_eq!((client.penalties(5), client.reputation(5), client.reputation(6)), (1, 0.5, 1.));
+
+        // On b, 3 is answered by 60% of the agents but they count half as much as the others
+        let other = responses(&[3, 3, 3, 3, 3, 3, 7, 7, 7, 7]);
+        assert_eq!(Client::new(0.4, Vec::new).guess("b", &other, 10), Some(3));
+        assert_eq!(client.guess("b", &other, 10), Some(7));
+
+        // A rejected value is held once against an agent
+        client.record("a", 10, answers);
+        assert_eq!((client.penalties(5), client.penalties(6)), (1, 1));
+
+        // The value found for a key is not held against the agents which answered it
+        let honest = responses(&[3, 3, 3, 3, 3, 3, 3, 1, 1, 1]);
+        assert_eq!(client.record("c", 10, honest.clone()).guess, Some(3));
+        client.set_found("c");
+        client.record("c", 10, honest);
+        assert_eq!(client.penalties(0), 1);
+
+        // Reputations rounding to 0 do not prevent from guessing
+        client.set_track_record(BTreeMap::from([("liar".to_string(), 200.)]));
+        client.identities.extend((0..10).map(|agent| (agent, "liar".to_string())));
+        assert_eq!(client.reputation(0), 0.);
+        assert_eq!(client.guess("d", &other, 10), Some(3));
+
+        // An honest agent recovers from a bad game, its penalties being spread over its games
+        let mut client = Client::<u16>::new(0.4, Vec::new);
+        client.identities.insert(0, "honest".to_string());
+        let mut records = Records::new();
+        merge(&mut records, &BTreeMap::from([("honest".to_string(), 2)]));
+        let mut reputations = Vec::new();
+        for _ in 0..4 {
+            client.set_track_record(penalties(&records));
+            reputations.push(client.reputation(0));
+            merge(&mut records, &BTreeMap::from([("honest".to_string(), 0)]));
+        }
+        assert_eq!(reputations[..2], [0.25, 0.5]);
+        assert!(reputations.windows(2).all(|pair| pair[0] < pair[1]));
+
+        // Agents which answered a rejected value in a previous round are penalized once it is rejected
+        let mut client = Client::new(0.4, Vec::new);
+        client.record("a", 10, responses(&[5, 5, 5, 5, 5, 5, 1, 1, 1, 1]));
+        client.record("a", 10, Vec::new());
+        assert_eq!(client.penalties(0), 1);
+    }
+
+    #[test]
+    fn test_track_record() {
+        let game = |seed : u64, identities : Vec<String>, track_record : BTreeMap<String, f32>| {
+            let values = BTreeMap::from([(String::new(), 3_u16)]);
+            let options = Options { identities, ..Options::default() };
+            let network = Arc::new(Network::spawn_with_registry(values, 1000, 10, 0.3, seed, &options, |_| {}).unwrap());
+            let mut client = {
+                let network = Arc::clone(&network);
+                Client::<u16>::new(0.3, move || network.ports())
+            };
+            client.set_track_record(track_record);
+            client.play("").unwrap();
+            network.shutdown();
+            client
+        };
+
+        // Every agent queried is identified, a new game with another seed keeps the identities given
+        let first = game(42, Vec::new(), BTreeMap::new());
+        assert_eq!(first.game_penalties().len(), 10);
+        assert!(first.game_penalties().values().all(|&penalties| penalties == 0));
+        let identities : Vec<String> = first.game_penalties().into_keys().collect();
+        let second = game(7, identities.clone(), BTreeMap::from([(identities[0].clone(), 2.)]));
+        assert_eq!(second.game_penalties(), first.game_penalties());
+
+        // The penalties of the previous games lower the reputation
+        let agent = second.history()[0].responses.iter().map(|r| r.agent).find(|&a| second.identity(a) == Some(identities[0].as_str())).unwrap();
+        assert_eq!((second.past_penalties(agent), second.reputation(agent)), (2., 0.25));
+        assert_eq!(Client::<u16>::new(0.3, Vec::new).identity(agent), None);
+
+        // The identities kept lie in every game, whatever the seed and the order of the agents
+        let liars = |client : &Client<u16>| -> Vec<String> {
+            let mut liars : Vec<String> = client.history()[0].responses.iter()
+                .filter(|r| r.value != 3)
+                .map(|r| client.identity(r.agent).unwrap().to_string())
+                .collect();
+            liars.sort();
+            liars
+        };
+        let third = game(99, identities.iter().rev().cloned().collect(), BTreeMap::new());
+        assert_eq!(liars(&second).len(), 3);
+        assert_eq!(liars(&third), liars(&second));
+
+        // A replay given the recorded track record and identities weighs the answers like the game
+        let answers = responses(&[3, 3, 3, 3, 3, 3, 7, 7, 7, 7]);
+        let mut replayed = Client::new(0.4, Vec::new);
+        replayed.set_track_record(BTreeMap::from([("liar".to_string(), 3.)]));
+        replayed.set_identities((0..6).map(|agent| (agent, "liar".to_string())).collect());
+        assert_eq!(replayed.record("", 10, answers.clone()).guess, Some(7));
+        assert_eq!(Client::new(0.4, Vec::new).record("", 10, answers).guess, Some(3));
+    }
+}
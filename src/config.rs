@@ -1,34 +1,84 @@
-use clap::{Arg, App};
-use std::fs::File;     
+use clap::{Arg, ArgAction, Command};
+use clap::parser::ValueSource;
+use std::env;
+use std::fmt;
+use std::fs::{self, File};
+use std::time::Duration;
 
 use std::io;
-use std::io::{BufRead, Write};
-use shellwords;
+use std::io::BufRead;
+use log::{debug, error, info};
+use crate::chaos::Schedule;
+use crate::topology::Topology;
+use crate::turncoat::{Trigger, Turncoats};
+use crate::value::ValueType;
+
+pub const MIN_AGENTS : usize = 2;
+pub const MAX_AGENTS : usize = 1000;
+pub const SETTINGS_FILE : &str = "liarslie.toml";       // Default settings file, LIARSLIE_CONFIG overrides it
+pub const ENV_PREFIX : &str = "LIARSLIE_";              // Prefix of the environment variables of the settings
 
 pub struct Config{
-    pub max_value : u16,
-    pub value : u16,
+    pub value_type : ValueType,        // Type of the network value
+    pub max_value : String,            // Maximum value as typed, parsed with value_type
+    pub value : String,                // Network value as typed, parsed with value_type. Empty with keys
+    pub keys : Vec<(String, String)>,  // (key, value as typed) of a key-value network, empty otherwise
     pub num_agents : u16,
     pub liar_ratio : f32,
+    pub record : Option<String>,       // Transcript file written during the game
+    pub seed : Option<u64>,            // Seed of the random number generators, random if None
+    pub topology : Topology,           // Who talks to whom among the agents
+    pub proxy : bool,                  // Routes the client through the proxy emulating a bad network
+    pub chaos : Option<Schedule>,      // Stops, restarts and freezes agents during every round if given
+    pub tui : bool,                    // Shows the agents and the answers on a dashboard redrawn in place
+    pub multiplayer : Option<usize>,   // Number of players of a competitive game, None for a single local client
+    pub port : u16,                    // Port of the game server in a competitive game
+    pub player : String,               // Name the scores are saved under
+    pub max_rounds : Option<usize>,    // Rounds before the game is lost, unlimited if None
+    pub query_budget : Option<usize>,  // Agents queried over the game before it is lost, unlimited if None
+    pub epochs : Option<Duration>,     // Time between two values pushed by the source, a fixed value if None
+    pub turncoats : Turncoats,         // Traitors and reformed liars, none by default
+    pub settings : Vec<Setting>,       // Every setting and where it came from, see the "config show" command
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source{
+    CommandLine,
+    Env(String),                        // Name of the environment variable
+    File(String),                       // Path of the settings file
+    Default,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Source::CommandLine => write!(f, "command line"),
+            Source::Env(var) => write!(f, "environment {}", var),
+            Source::File(path) => write!(f, "file {}", path),
+            Source::Default => write!(f, "default"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Setting{
+    pub name : String,                  // Name of the flag, e.g. num-agents
+    pub value : String,                 // Value as typed, "true" or "false" for the flags
+    pub source : Source,
 }
 
 pub fn write_config_file(ports : Vec<u16>){
     /*
-        Writes agent.config with the port numbers of all the agents.
+        Writes agent.config with the port numbers of all the agents. The ports are written to a
+        temporary file renamed to agent.config, so that a client never reads a file being written.
+        It is called from the supervisor's thread, an error is only logged.
 
         Args : 
             - ports : port number of every agent
     */
-    let mut f = File::create("agent.config").expect("Unable to create file");                                                                                                          
-    for i in &ports{                                                                                                                                                                  
-        let write = write!(f, "{}\n", i); 
-        match write {
-            Ok(_) => {},
-            Err(e) => {
-                println!("Error happened while writing config file {}", e); 
-                std::process::exit(1)
-            },
-        };    
+    let content : String = ports.iter().map(|port| format!("{}\n", port)).collect();
+    if let Err(e) = fs::write("agent.config.tmp", content).and_then(|_| fs::rename("agent.config.tmp", "agent.config")) {
+        error!("Error happened while writing config file {}", e);
     }
 }
 
@@ -47,15 +97,15 @@ pub fn read_config_file() -> Vec<u16> {
                         let int = ip.parse::<u16>();
                         match int {
                             Ok(u) => vec.push(u),
-                            Err(e) => println!("Error {}", e),
+                            Err(e) => error!("Invalid port in agent.config {}", e),
                         }
                     }
 
-                    Err(e) => {println!("Error {}", e)},
+                    Err(e) => {error!("Error reading agent.config {}", e)},
                 }
             }
         },
-        Err(e) => {println!("err : {}", e); std::process::exit(1);},
+        Err(e) => {error!("Unable to open agent.config : {}", e); std::process::exit(1);},
     }
     vec
 }
@@ -64,174 +114,534 @@ pub fn delete_config_file(){
     /*
         Deletes agent.config
     */
-    println!("deleting agent.config");
+    debug!("deleting agent.config");
     match std::fs::remove_file("agent.config"){
-        Ok(_) => println!("File agent.config successfully deleted"),
-        Err(e) => println!("An error has occurred {}", e),
+        Ok(_) => info!("File agent.config successfully deleted"),
+        Err(e) => error!("An error has occurred while deleting agent.config {}", e),
+    };
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigError{
+    Command(String),                    // The command cannot be split into words, e.g. an unclosed quote
+    Arguments(String),                  // Unknown, missing or repeated flags as reported by clap, or the help
+    ValueType(String),                  // Unknown value type
+    Value(Option<String>, String),      // (key, problem) Value or max-value which is not valid for the value type
+    ValueAboveMax(Option<String>, String, String),  // (key, value, max-value) Value above max-value
+    KeyFormat(String),                  // Item of --keys which is not key=value
+    DuplicateKey(String),               // Key given twice in --keys
+    NumAgents(String),                  // num-agents which is not an integer
+    NumAgentsOutOfRange(usize),         // num-agents not in [2 ; 1000]
+    LiarRatio(String),                  // liar-ratio which is not a float
+    LiarRatioOutOfRange(f32),           // liar-ratio not in [0 ; 1[
+    NoLiar(f32),                        // liar-ratio * num-agents < 1, there is always an honest agent as liar-ratio < 1
+    Seed(String),                       // seed which is not a 64b integer
+    Topology(String),                   // Topology which cannot be parsed or built
+    Chaos(String),                      // Invalid chaos schedule
+    Players(String),                    // multiplayer not in [1 ; 100]
+    MultiplayerWithKeys,                // multiplayer with --keys
+    Port(String),                       // port which is not a 16b integer
+    EmptyPlayer,                        // Empty player name
+    MaxRounds(String),                  // max-rounds which is not a positive integer
+    QueryBudget(String),                // query-budget which is not a positive integer
+    MultiplayerWithBudget,              // multiplayer with --max-rounds or --query-budget
+    Epochs(String),                     // epochs which is not a positive number of milliseconds
+    MultiplayerWithEpochs,              // multiplayer with --epochs
+    Traitors(String),                   // traitors which is not a ratio in [0 ; 1]
+    Reformed(String),                   // reformed which is not a ratio in [0 ; 1]
+    Turn(String),                       // turn which is neither a number of queries nor random:<ms>
+    TurnWithoutTurncoats,               // turn without --traitors nor --reformed
+    File(String, String),               // (path, problem) Settings file which cannot be read
+    Env(String, String),                // (variable, problem) Environment variable which is not valid
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        let key = |key : &Option<String>| key.as_ref().map(|key| format!("key {} : ", key)).unwrap_or_default();
+        match self {
+            ConfigError::Command(e) => write!(f, "the command cannot be read : {}", e),
+            ConfigError::Arguments(e) => write!(f, "{}", e.trim_end()),
+            ConfigError::ValueType(e) => write!(f, "{}", e),
+            ConfigError::Value(k, e) => write!(f, "{}{}", key(k), e),
+            ConfigError::ValueAboveMax(k, value, max) => write!(f, "{}value should be at most max-value, got value {} and max-value {}", key(k), value, max),
+            ConfigError::KeyFormat(item) => write!(f, "keys should be a comma separated list of key=value without spaces in keys, got {}", item),
+            ConfigError::DuplicateKey(k) => write!(f, "key {} is given twice", k),
+            ConfigError::NumAgents(n) => write!(f, "num-agents should be an integer, got {}", n),
+            ConfigError::NumAgentsOutOfRange(n) => write!(f, "num-agents should be in [{} ; {}], got {}", MIN_AGENTS, MAX_AGENTS, n),
+            ConfigError::LiarRatio(r) => write!(f, "liar-ratio should be a float, got {}", r),
+            ConfigError::LiarRatioOutOfRange(r) => write!(f, "liar-ratio should be in [0 ; 1[, got {}", r),
+            ConfigError::NoLiar(r) => write!(f, "liar-ratio {} gives no liar, there must be at least one", r),
+            ConfigError::Seed(s) => write!(f, "seed should be a 64b integer, got {}", s),
+            ConfigError::Topology(e) => write!(f, "{}", e),
+            ConfigError::Chaos(e) => write!(f, "{}", e),
+            ConfigError::Players(p) => write!(f, "multiplayer should be a number of players in [1 ; 100], got {}", p),
+            ConfigError::MultiplayerWithKeys => write!(f, "multiplayer games are played on a single --value"),
+            ConfigError::Port(p) => write!(f, "port should be a 16b integer, got {}", p),
+            ConfigError::EmptyPlayer => write!(f, "player should not be empty"),
+            ConfigError::MaxRounds(r) => write!(f, "max-rounds should be a positive integer, got {}", r),
+            ConfigError::QueryBudget(q) => write!(f, "query-budget should be a positive integer, got {}", q),
+            ConfigError::MultiplayerWithBudget => write!(f, "multiplayer games have no round limit nor query budget"),
+            ConfigError::Epochs(e) => write!(f, "epochs should be a positive number of milliseconds, got {}", e),
+            ConfigError::MultiplayerWithEpochs => write!(f, "multiplayer games are played on a fixed value"),
+            ConfigError::Traitors(r) => write!(f, "traitors should be a ratio of the honest agents in [0 ; 1], got {}", r),
+            ConfigError::Reformed(r) => write!(f, "reformed should be a ratio of the liars in [0 ; 1], got {}", r),
+            ConfigError::Turn(e) => write!(f, "{}", e),
+            ConfigError::TurnWithoutTurncoats => write!(f, "turn needs --traitors or --reformed"),
+            ConfigError::File(path, e) => write!(f, "settings file {} : {}", path, e),
+            ConfigError::Env(var, e) => write!(f, "{} {}", var, e),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+fn command() -> Command {
+    /*
+        The start command and its flags, without the rules between them : the settings of the
+        command line may be completed by the settings file and the environment.
+    */
+    Command::new("liarlies")
+        .override_usage("start --value <v> --max-value <max> --num-agents <number> --liar-ratio <ratio>")
+        .version("1.0.0")
+        .author("Benabdallah Ali")
+        .arg(Arg::new("value")
+                .long("value")
+                .allow_hyphen_values(true)
+                .help("True value, integer in [1 ; max-value] for the default type")
+                .conflicts_with("keys"))
+        .arg(Arg::new("keys")
+                .long("keys")
+                .help("Key-value network, comma separated list of key=value. Replaces --value"))
+        .arg(Arg::new("max-value")
+                .long("max-value")
+                .help("Maximum value, integer in [2 ; 65535] for the default type. Not used for strings"))
+        .arg(Arg::new("value-type")
+                .long("value-type")
+                .help("Type of the network value : u16 (default), u32, u64, i64 or string"))
+        .arg(Arg::new("num-agents")
+                .long("num-agents")
+                .help("Number of agents, integer in [2 ; 1000]."))
+        .arg(Arg::new("liar-ratio")
+                .long("liar-ratio")
+                .help("Ratio of liars, float in [0 ; 1[. There must be at least one liar"))
+        .arg(Arg::new("record")
+                .long("record")
+                .help("Writes the transcript of the game to this file, see the replay command"))
+        .arg(Arg::new("seed")
+                .long("seed")
+                .help("Seed of the random number generators, 64b integer. Random if not given"))
+        .arg(Arg::new("topology")
+                .long("topology")
+                .help("Who talks to whom among the agents : complete (default), ring, regular:<k>, small-world:<k>:<p> or file:<path>"))
+        .arg(Arg::new("proxy")
+                .long("proxy")
+                .action(ArgAction::SetTrue)
+                .help("Routes the client through a proxy emulating latency, losses and partitions, see the net command"))
+        .arg(Arg::new("chaos")
+                .long("chaos")
+                .help("Stops, restarts and freezes agents during every round : default or interval=<ms>,stop=<p>,restart=<p>,freeze=<p>,freeze-for=<ms>,revive=<p>"))
+        .arg(Arg::new("tui")
+                .long("tui")
+                .action(ArgAction::SetTrue)
+                .help("Shows a dashboard of the agents and of the answers during every round"))
+        .arg(Arg::new("multiplayer")
+                .long("multiplayer")
+                .help("Hosts a competitive game between this number of players, see the join and bot commands"))
+        .arg(Arg::new("port")
+                .long("port")
+                .help("Port of the game server on localhost, 9000 by default"))
+        .arg(Arg::new("player")
+                .long("player")
+                .help("Name the score is saved under when the value is found, $USER by default"))
+        .arg(Arg::new("max-rounds")
+                .long("max-rounds")
+                .help("Rounds before the game is lost, unlimited by default"))
+        .arg(Arg::new("query-budget")
+                .long("query-budget")
+                .help("Agents queried over the game before it is lost, spread across the rounds. Unlimited by default"))
+        .arg(Arg::new("epochs")
+                .long("epochs")
+                .help("Pushes a new network value to the agents every this number of milliseconds, see the epochs"))
+        .arg(Arg::new("traitors")
+                .long("traitors")
+                .help("Ratio of the honest agents of every key which start lying when they turn, in [0 ; 1]"))
+        .arg(Arg::new("reformed")
+                .long("reformed")
+                .help("Ratio of the liars of every key which start telling the truth when they turn, in [0 ; 1]"))
+        .arg(Arg::new("turn")
+                .long("turn")
+                .help("When the traitors and the reformed liars turn : after this number of queries (3 by default) or random:<ms>"))
+}
+
+fn complete_command() -> Command {
+    /*
+        The start command with the flags which are required and the flags which require another one.
+    */
+    command()
+        .mut_arg("value", |arg| arg.required_unless_present("keys"))
+        .mut_arg("num-agents", |arg| arg.required(true))
+        .mut_arg("liar-ratio", |arg| arg.required(true))
+        .mut_arg("port", |arg| arg.requires("multiplayer"))
+}
+
+fn is_flag(arg : &Arg) -> bool {
+    !arg.get_action().takes_values()
+}
+
+fn is_flag_name(name : &str) -> bool {
+    command().get_arguments().any(|arg| arg.get_id() == name && is_flag(arg))
+}
+
+fn default(name : &str) -> Option<String> {
+    /*
+        Returns the value of a setting which is given nowhere, None if it has no default.
+    */
+    match name {
+        "value-type" => Some("u16".to_string()),
+        "topology" => Some("complete".to_string()),
+        "port" => Some("9000".to_string()),
+        "player" => Some(env::var("USER").unwrap_or_else(|_| "anonymous".to_string())),
+        _ => None,
+    }
+}
+
+pub fn env_var(name : &str) -> String {
+    /*
+        Returns the environment variable of a setting, e.g. LIARSLIE_NUM_AGENTS for num-agents.
+    */
+    format!("{}{}", ENV_PREFIX, name.to_uppercase().replace('-', "_"))
+}
+
+pub fn settings_file() -> String {
+    /*
+        Returns the settings file : $LIARSLIE_CONFIG or SETTINGS_FILE.
+    */
+    env::var("LIARSLIE_CONFIG").unwrap_or_else(|_| SETTINGS_FILE.to_string())
+}
+
+fn read_settings(path : &str, content : &str) -> Result<Vec<(String, String)>, ConfigError> {
+    /*
+        Reads the settings of a TOML file, named like the flags :
+            num-agents = 10
+            liar-ratio = 0.3
+            tui = true
+            [keys]
+            a = 3
+
+        Args :
+            - path : path of the file, for the errors
+            - content : content of the file
+        Returns :
+            - Vec<(String, String)> : (name, value) of every setting, the keys as key=value,...
+    */
+    let problem = |e : String| ConfigError::File(path.to_string(), e);
+    let table : toml::Table = content.parse().map_err(|e : toml::de::Error| problem(e.message().to_string()))?;
+    let scalar = |name : &str, value : &toml::Value| match value {
+        toml::Value::String(s) => Ok(s.clone()),
+        toml::Value::Integer(_) | toml::Value::Float(_) | toml::Value::Boolean(_) => Ok(value.to_string()),
+        _ => Err(problem(format!("{} should be a string, a number or a boolean", name))),
+    };
+    let mut settings = Vec::new();
+    for (name, value) in &table {
+        if !command().get_arguments().any(|arg| arg.get_id() == name.as_str()) {
+            return Err(problem(format!("unknown setting {}", name)));
+        }
+        let value = match value {
+            toml::Value::Table(keys) if name == "keys" => {
+                keys.iter().map(|(key, value)| Ok(format!("{}={}", key, scalar(key, value)?))).collect::<Result<Vec<String>, ConfigError>>()?.join(",")
+            },
+            value => scalar(name, value)?,
+        };
+        settings.push((name.clone(), value));
+    }
+    Ok(settings)
+}
+
+fn layered<E : Fn(&str) -> Option<String>>(words : Vec<String>, file : &str, from_file : Vec<(String, String)>, env : E) -> Result<Vec<Setting>, ConfigError> {
+    /*
+        Returns the settings of the command line, completed by the environment, then by the settings
+        file, then by the defaults. --value and --keys replace each other.
+
+        Args :
+            - words : command split into words
+            - file : path of the settings file
+            - from_file : (name, value) of the settings of the file
+            - env : value of an environment variable
+        Returns :
+            - Vec<Setting> : every setting, in the order of the flags of the start command
+    */
+    let command = command();
+    let matches = command.clone().try_get_matches_from(words).map_err(|e| ConfigError::Arguments(e.to_string()))?;
+    let mut settings : Vec<Setting> = Vec::new();
+    let mut add = |name : &str, value : String, source : Source| {
+        let replaced = |other : &str| other == name || (name == "value" && other == "keys") || (name == "keys" && other == "value");
+        if !settings.iter().any(|s| replaced(&s.name)) {
+            settings.push(Setting { name : name.to_string(), value, source });
+        }
+    };
+    let boolean = |value : &str| match value {
+        "true" | "1" | "yes" => Ok("true".to_string()),
+        "false" | "0" | "no" => Ok("false".to_string()),
+        _ => Err(format!("should be true or false, got {}", value)),
+    };
+
+    for arg in command.get_arguments() {
+        let name = arg.get_id().as_str();
+        if matches.value_source(name) == Some(ValueSource::CommandLine) {
+            let value = if is_flag(arg) { "true".to_string() } else { matches.get_one::<String>(name).cloned().unwrap_or_default() };
+            add(name, value, Source::CommandLine);
+        }
+    }
+    for arg in command.get_arguments() {
+        let name = arg.get_id().as_str();
+        let var = env_var(name);
+        if let Some(value) = env(&var) {
+            let value = if is_flag(arg) { boolean(&value).map_err(|e| ConfigError::Env(var.clone(), e))? } else { value };
+            add(name, value, Source::Env(var));
+        }
+    }
+    for (name, value) in from_file {
+        let value = if is_flag_name(&name) { boolean(&value).map_err(|e| ConfigError::File(file.to_string(), format!("{} {}", name, e)))? } else { value };
+        add(&name, value, Source::File(file.to_string()));
+    }
+    for arg in command.get_arguments() {
+        let name = arg.get_id().as_str();
+        if let Some(value) = default(name) {
+            add(name, value, Source::Default);
+        }
+    }
+
+    let position = |name : &str| command.get_arguments().position(|arg| arg.get_id() == name);
+    settings.sort_by_key(|s| position(&s.name));
+    Ok(settings)
+}
+
+pub fn settings(words : Vec<String>) -> Result<Vec<Setting>, ConfigError> {
+    /*
+        Returns the settings of a start command completed by the LIARSLIE_* environment variables and
+        the settings file, which may not exist.
+    */
+    let path = settings_file();
+    let from_file = match fs::read_to_string(&path) {
+        Ok(content) => read_settings(&path, &content)?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(ConfigError::File(path, e.to_string())),
     };
+    layered(words, &path, from_file, |var| env::var(var).ok())
+}
+
+pub fn show(settings : &[Setting]) -> String {
+    /*
+        Returns the settings and where each one came from, for the "config show" command.
+    */
+    let width = settings.iter().map(|s| s.name.len()).max().unwrap_or(0);
+    settings.iter().map(|s| format!("{:<width$} = {:<16} ({})\n", s.name, s.value, s.source, width = width)).collect()
 }
 
 impl Config {
 
-    pub fn new() -> Self {
+    pub fn values(&self) -> Vec<(String, String)> {
         /*
-            Reads the std::in and waits for a command of structure :
-                start --value <v> --max-value <max> --num-agents <number> --liar-ratio <ratio>
-            Parse and checks that all the values are correct otherwise displays the correct usage and
-            exits the program with code 1.
+            Returns the (key, value) pairs held by the network. A network with a single value
+            holds it under the empty key.
+        */
+        if self.keys.is_empty() {
+            vec![(String::new(), self.value.clone())]
+        } else {
+            self.keys.clone()
+        }
+    }
 
+    pub fn new(input : &str) -> Result<Self, ConfigError> {
+        /*
+            Parses a command read on stdin of structure :
+                start --value <v> --max-value <max> --num-agents <number> --liar-ratio <ratio>
+                      [--value-type <type>] [--record <file>] [--seed <seed>] [--topology <topology>] [--proxy] [--chaos <schedule>] [--tui]
+                      [--multiplayer <players> [--port <port>]] [--player <name>] [--max-rounds <rounds>] [--query-budget <queries>]
+                      [--epochs <ms>] [--traitors <ratio>] [--reformed <ratio>] [--turn <queries|random:ms>]
+            or, for a key-value network :
+                start --keys <key=value,...> --max-value <max> --num-agents <number> --liar-ratio <ratio> ...
+            and checks that all the values are correct.
+
+            Args :
+                - input : command line typed by the user
             Returns : 
-                - Config structure from parsed input
+                - Config structure from parsed input, or the first problem found
 
             Example : // Exemple : start --value 1 --max-value 3 --num-agents 10 --liar-ratio 0.5
         */
+        let words = shellwords::split(input).map_err(|e| ConfigError::Command(e.to_string()))?;
+        Self::load(words)
+    }
 
-        
-        let mut input = String::new();
-        let mut problem_found = false;
-        
-        let value : u16;
-        let max_value : u16;
-        let num_agents : u16;
-        let liar_ratio : f32;
-
-        /* ---- Read the stdin ---- */
-
-        io::stdout().flush().expect("Couldn't flush stdout");
-        io::stdin().read_line(&mut input).expect("Error reading input.");
-        let words = shellwords::split(&input).unwrap();
-        
-        /* ---- Parses the command with clap ---- */
-        let app = 
-            App::new("liarlies")
-            .usage("start --value <v> --max-value <max> --num-agents <number> --liar-ratio <ratio>")
-            .version("1.0.0")
-            .author("Benabdallah Ali")
-            .arg(Arg::with_name("value")
-                    .long("value")
-                    .takes_value(true)
-                    .help("True value, integer in [1 ; 65535]")
-                    .required(true))
-            .arg(Arg::with_name("max-value")
-                    .long("max-value")
-                    .takes_value(true)
-                    .help("Maximum value, integer in [2 ; 65535]")
-                    .required(true))
-            .arg(Arg::with_name("num-agents")
-                    .long("num-agents")
-                    .takes_value(true)
-                    .help("Number of agents, integer in [2 ; 1000].")
-                    .required(true))
-            .arg(Arg::with_name("liar-ratio")
-                    .long("liar-ratio")
-                    .takes_value(true)
-                    .help("Ratio of liars, float in [0 ; 1[. There must be at least one liar")
-                    .required(true));
-        let matches = app.get_matches_from(words);
+    pub fn load(words : Vec<String>) -> Result<Self, ConfigError> {
+        /*
+            Parses the words of a start command, the missing settings being read in the LIARSLIE_*
+            environment variables then in the settings file.
+        */
+        Self::from_settings(settings(words)?)
+    }
 
-        /* ---- Get the value and sanity check ---- */
-        
-        value = match matches.value_of("value") {
-            None => {problem_found = true; 0},
-            Some(s) => {
-                match s.parse::<u16>(){
-                    Ok(n) => {
-                        if n == 0{
-                            println!("value should be in [1; 65535]");
-                            problem_found = true;
-                        }
-                        n
-                    },
-                    Err(_) => {
-                        println!("value should be a 16b integer");
-                        problem_found = true; 
-                        0
-                    }
-                }
-            }
-        };
+    pub fn parse(words : Vec<String>) -> Result<Self, ConfigError> {
+        /*
+            Parses the words of a start command, the first one being "start", ignoring the environment
+            and the settings file.
 
-        max_value = match matches.value_of("max-value") {
-            None => {problem_found = true; 0},
-            Some(s) => {
-                match s.parse::<u16>(){
-                    Ok(n) => {
-                        if n < 2{
-                            println!("max-value should be in [2;65535]");
-                            problem_found = true;
-                        }
-                        
-                        n
-                    },
-                    Err(_) => {
-                        println!("max-value should be a 16b integer");
-                        problem_found = true; 
-                        0
-                    }
-                }
+            Args :
+                - words : command split into words
+            Returns :
+                - Config structure, or the first problem found in the command
+        */
+        Self::from_settings(layered(words, SETTINGS_FILE, Vec::new(), |_| None)?)
+    }
+
+    fn from_settings(settings : Vec<Setting>) -> Result<Self, ConfigError> {
+        /*
+            Checks the settings and builds the Config structure. --port requires --multiplayer only
+            on the command line : a port read in the environment or the settings file is meant for
+            competitive games and is ignored by the others.
+        */
+        let multiplayer = settings.iter().any(|s| s.name == "multiplayer" && s.source != Source::Default);
+        let ignored = |s : &Setting| s.name == "port" && s.source != Source::CommandLine && !multiplayer;
+        let mut words = vec!["start".to_string()];
+        for setting in settings.iter().filter(|s| s.source != Source::Default && !ignored(s)) {
+            match setting.value.as_str() {
+                "true" if is_flag_name(&setting.name) => words.push(format!("--{}", setting.name)),
+                "false" if is_flag_name(&setting.name) => {},
+                value => words.push(format!("--{}={}", setting.name, value)),
             }
-        };
+        }
+        let matches = complete_command().try_get_matches_from(words).map_err(|e| ConfigError::Arguments(e.to_string()))?;
+        let get = |name : &str| matches.get_one::<String>(name).cloned().or_else(|| default(name));
 
-        num_agents = match matches.value_of("num-agents") {
-            None => {problem_found = true; 0},
-            Some(s) => {
-                match s.parse::<u16>(){
-                    Ok(n) => {
-                        if n < 2{
-                            println!("num-agents should be in [2;1000]");
-                            problem_found = true;
-                        }
-                        n
-                    },
-                    Err(_) => {
-                        println!("value should be a 16b integer");
-                        problem_found = true; 
-                        0
-                    }
-                }
+        /* ---- Get the value and sanity check ---- */
+
+        let value_type = get("value-type").unwrap_or_default().parse::<ValueType>().map_err(ConfigError::ValueType)?;
+        let value = get("value").unwrap_or_default();
+        let max_value = get("max-value").unwrap_or_default();
+        let check = |key : Option<&str>, value : &str| {
+            let key = key.map(|k| k.to_string());
+            value_type.check(value, &max_value).map_err(|e| ConfigError::Value(key.clone(), e))?;
+            if value_type.exceeds(value, &max_value) {
+                return Err(ConfigError::ValueAboveMax(key, value.to_string(), max_value.clone()));
             }
+            Ok(())
         };
 
-        liar_ratio = match matches.value_of("liar-ratio") {
-            None => {problem_found = true; 0.0f32},
+        let mut keys : Vec<(String, String)> = Vec::new();
+        match get("keys") {
             Some(s) => {
-                match s.parse::<f32>(){
-                    Ok(n) => {
-                        let number_of_liars = (n * (num_agents as f32)) as u16;
-                        if n < 0. || n >= 1.-1e-9 || number_of_liars < 1 || number_of_liars == num_agents {
-                            println!("liar_ratio should be in [0,1[ with at least one liar and one honest agent. value : {}, number_of_liars {}", n, number_of_liars);
-                            problem_found = true;
-                        }
-                        n
-                    },
-                    Err(_) => {
-                        println!("liar_ratio should be a float");
-                        problem_found = true; 
-                        0.0f32
+                for item in s.split(',') {
+                    match item.split_once('=') {
+                        Some((key, val)) if !key.is_empty() && !key.contains(char::is_whitespace) => {
+                            if keys.iter().any(|(k, _)| k == key) {
+                                return Err(ConfigError::DuplicateKey(key.to_string()));
+                            }
+                            check(Some(key), val)?;
+                            keys.push((key.to_string(), val.to_string()));
+                        },
+                        _ => return Err(ConfigError::KeyFormat(item.to_string())),
                     }
                 }
-            }
+            },
+            None => check(None, &value)?,
+        }
+
+        let num_agents = get("num-agents").unwrap_or_default();
+        let num_agents = num_agents.parse::<usize>().map_err(|_| ConfigError::NumAgents(num_agents))?;
+        if !(MIN_AGENTS..=MAX_AGENTS).contains(&num_agents) {
+            return Err(ConfigError::NumAgentsOutOfRange(num_agents));
+        }
+        let num_agents = num_agents as u16;
+
+        let liar_ratio = get("liar-ratio").unwrap_or_default();
+        let liar_ratio = liar_ratio.parse::<f32>().map_err(|_| ConfigError::LiarRatio(liar_ratio))?;
+        if !(0. ..1.-1e-9).contains(&liar_ratio) {
+            return Err(ConfigError::LiarRatioOutOfRange(liar_ratio));
+        }
+        let number_of_liars = (liar_ratio * (num_agents as f32)) as u16;
+        if number_of_liars < 1 {
+            return Err(ConfigError::NoLiar(liar_ratio));
+        }
+
+        info!("value_type {:?}", value_type);
+        info!("max_value {}", max_value);
+        info!("value {}", value);
+        info!("keys {:?}", keys);
+        info!("num_agents {}", num_agents);
+        info!("liar_ratio : {}", liar_ratio);
+
+        let record = get("record");
+
+        let seed = get("seed").map(|s| s.parse::<u64>().map_err(|_| ConfigError::Seed(s))).transpose()?;
+
+        let topology = get("topology").unwrap_or_default().parse::<Topology>().map_err(ConfigError::Topology)?;
+        topology.check(num_agents as usize).map_err(ConfigError::Topology)?;
+
+        let chaos = get("chaos").map(|s| s.parse::<Schedule>().map_err(ConfigError::Chaos)).transpose()?;
+
+        let multiplayer = match get("multiplayer") {
+            Some(s) => match s.parse::<usize>() {
+                Ok(players) if (1..=100).contains(&players) => Some(players),
+                _ => return Err(ConfigError::Players(s)),
+            },
+            None => None,
         };
+        if multiplayer.is_some() && !keys.is_empty() {
+            return Err(ConfigError::MultiplayerWithKeys);
+        }
 
-        println!("max_value {}", max_value);
-        println!("value {}", value);
-        println!("num_agents {}", num_agents);
-        println!("liar_ratio : {}", liar_ratio);
+        let port = get("port").unwrap_or_default();
+        let port = port.parse::<u16>().map_err(|_| ConfigError::Port(port))?;
 
-        // If it finds a problem, it exits the loop.
-        // It can be done properly with try_get_matches() from clap 3.* but I started with 2.* 
+        let player = get("player").unwrap_or_default();
+        if player.trim().is_empty() {
+            return Err(ConfigError::EmptyPlayer);
+        }
 
-        if problem_found {
-            std::process::exit(1);
+        let positive = |s : &String| s.parse::<usize>().ok().filter(|&n| n > 0);
+        let max_rounds = get("max-rounds").map(|s| positive(&s).ok_or(ConfigError::MaxRounds(s))).transpose()?;
+        let query_budget = get("query-budget").map(|s| positive(&s).ok_or(ConfigError::QueryBudget(s))).transpose()?;
+        if multiplayer.is_some() && (max_rounds.is_some() || query_budget.is_some()) {
+            return Err(ConfigError::MultiplayerWithBudget);
         }
-            
-        Self {     
-            max_value : max_value,
-            value : value,
-            num_agents : num_agents,
-            liar_ratio : liar_ratio,
+
+        let epochs = get("epochs").map(|s| positive(&s).map(|ms| Duration::from_millis(ms as u64)).ok_or(ConfigError::Epochs(s))).transpose()?;
+        if multiplayer.is_some() && epochs.is_some() {
+            return Err(ConfigError::MultiplayerWithEpochs);
+        }
+
+        let ratio = |s : &String| s.parse::<f32>().ok().filter(|r| (0. ..=1.).contains(r));
+        let traitors = get("traitors").map(|s| ratio(&s).ok_or(ConfigError::Traitors(s))).transpose()?.unwrap_or(0.);
+        let reformed = get("reformed").map(|s| ratio(&s).ok_or(ConfigError::Reformed(s))).transpose()?.unwrap_or(0.);
+        let trigger = get("turn").map(|s| s.parse::<Trigger>().map_err(ConfigError::Turn)).transpose()?;
+        let turncoats = Turncoats { traitors, reformed, trigger : trigger.unwrap_or_default() };
+        if trigger.is_some() && turncoats.is_none() {
+            return Err(ConfigError::TurnWithoutTurncoats);
         }
 
+        Ok(Self {
+            value_type,
+            max_value,
+            value,
+            keys,
+            num_agents,
+            liar_ratio,
+            record,
+            seed,
+            topology,
+            proxy : matches.get_flag("proxy"),
+            chaos,
+            tui : matches.get_flag("tui"),
+            multiplayer,
+            port,
+            player,
+            max_rounds,
+            query_budget,
+            epochs,
+            turncoats,
+            settings,
+        })
     }
 
 }
@@ -239,9 +649,9 @@ impl Config {
 /*---------------------------- TESTS ----------------------------*/
 
 
-
+#[cfg(test)]
 mod tests {
-    use crate::config::{write_config_file, read_config_file, delete_config_file};
+    use crate::config::{Config, ConfigError, Source, layered, read_settings, write_config_file, read_config_file, delete_config_file};
     use std::collections::HashSet;
     use std::path::Path;
 
@@ -266,5 +676,95 @@ mod tests {
         assert!(!Path::new("./agent.config").exists());
 
     }
-}
 
+    #[test]
+    fn test_config_errors() {
+        // Config::parse ignores the LIARSLIE_* variables and liarslie.toml of the machine running the tests
+        let parse = |args : &str| Config::parse(shellwords::split(&format!("start {}", args)).unwrap()).err();
+        let game = "--max-value 10 --num-agents 10 --liar-ratio 0.3";
+
+        assert_eq!(parse(&format!("--value 5 {}", game)), None);
+        assert_eq!(parse(&format!("--value 5 {} --multiplayer 2 --port 9100", game)), None);
+        assert!(matches!(parse("--value 5 --num-agents 10"), Some(ConfigError::Arguments(_))));
+        assert!(matches!(parse(&format!("--value 5 {} --color red", game)), Some(ConfigError::Arguments(_))));
+        assert!(matches!(parse(&format!("--value 5 {} --port 9100", game)), Some(ConfigError::Arguments(_))));
+        // The command is split before any setting is read
+        assert!(matches!(Config::new("start --value '5"), Err(ConfigError::Command(_))));
+        assert!(matches!(parse(&format!("--value 5 {} --value-type u8", game)), Some(ConfigError::ValueType(_))));
+        assert_eq!(parse(&format!("--value 0 {}", game)), Some(ConfigError::Value(None, "value should be in [1 ; 65535]".to_string())));
+        assert_eq!(parse(&format!("--value 11 {}", game)), Some(ConfigError::ValueAboveMax(None, "11".to_string(), "10".to_string())));
+        assert_eq!(parse(&format!("--keys a=1,b=11 {}", game)), Some(ConfigError::ValueAboveMax(Some("b".to_string()), "11".to_string(), "10".to_string())));
+        assert_eq!(parse(&format!("--keys a=1,b {}", game)), Some(ConfigError::KeyFormat("b".to_string())));
+        assert_eq!(parse(&format!("--keys a=1,a=2 {}", game)), Some(ConfigError::DuplicateKey("a".to_string())));
+        assert_eq!(parse("--value 5 --max-value 10 --num-agents ten --liar-ratio 0.3"), Some(ConfigError::NumAgents("ten".to_string())));
+        assert_eq!(parse("--value 5 --max-value 10 --num-agents 1 --liar-ratio 0.3"), Some(ConfigError::NumAgentsOutOfRange(1)));
+        assert_eq!(parse("--value 5 --max-value 10 --num-agents 1001 --liar-ratio 0.3"), Some(ConfigError::NumAgentsOutOfRange(1001)));
+        assert_eq!(parse("--value 5 --max-value 10 --num-agents 10 --liar-ratio a"), Some(ConfigError::LiarRatio("a".to_string())));
+        assert_eq!(parse("--value 5 --max-value 10 --num-agents 10 --liar-ratio 1.5"), Some(ConfigError::LiarRatioOutOfRange(1.5)));
+        assert_eq!(parse("--value 5 --max-value 10 --num-agents 10 --liar-ratio 0.05"), Some(ConfigError::NoLiar(0.05)));
+        assert_eq!(parse(&format!("--value 5 {} --seed x1", game)), Some(ConfigError::Seed("x1".to_string())));
+        assert!(matches!(parse(&format!("--value 5 {} --topology star", game)), Some(ConfigError::Topology(_))));
+        assert!(matches!(parse(&format!("--value 5 {} --chaos stop=2", game)), Some(ConfigError::Chaos(_))));
+        assert_eq!(parse(&format!("--value 5 {} --multiplayer 0", game)), Some(ConfigError::Players("0".to_string())));
+        assert_eq!(parse(&format!("--keys a=1 {} --multiplayer 2", game)), Some(ConfigError::MultiplayerWithKeys));
+        assert_eq!(parse(&format!("--value 5 {} --multiplayer 2 --port 70000", game)), Some(ConfigError::Port("70000".to_string())));
+        assert_eq!(parse(&format!("--value 5 {} --player ''", game)), Some(ConfigError::EmptyPlayer));
+        assert_eq!(parse(&format!("--value 5 {} --max-rounds 0", game)), Some(ConfigError::MaxRounds("0".to_string())));
+        assert_eq!(parse(&format!("--value 5 {} --query-budget many", game)), Some(ConfigError::QueryBudget("many".to_string())));
+        assert_eq!(parse(&format!("--value 5 {} --multiplayer 2 --max-rounds 3", game)), Some(ConfigError::MultiplayerWithBudget));
+        assert_eq!(parse(&format!("--value 5 {} --epochs 0", game)), Some(ConfigError::Epochs("0".to_string())));
+        assert_eq!(parse(&format!("--value 5 {} --multiplayer 2 --epochs 100", game)), Some(ConfigError::MultiplayerWithEpochs));
+        assert_eq!(parse(&format!("--value 5 {} --traitors 1.5", game)), Some(ConfigError::Traitors("1.5".to_string())));
+        assert_eq!(parse(&format!("--value 5 {} --reformed half", game)), Some(ConfigError::Reformed("half".to_string())));
+        assert!(matches!(parse(&format!("--value 5 {} --traitors 0.5 --turn later", game)), Some(ConfigError::Turn(_))));
+        assert_eq!(parse(&format!("--value 5 {} --turn 2", game)), Some(ConfigError::TurnWithoutTurncoats));
+        assert_eq!(parse(&format!("--value 5 {} --reformed 1 --turn random:100", game)), None);
+        assert_eq!(parse(&format!("--value 5 {}", game.replace("10 --liar", "1000 --liar"))), None);
+    }
+
+    #[test]
+    fn test_config_settings() {
+        let file = "max-value = 10\nnum-agents = 8\nliar-ratio = 0.3\ntui = true\n[keys]\na = 3\nb = \"7\"";
+        let from_file = read_settings("liarslie.toml", file).unwrap();
+        assert!(from_file.contains(&("keys".to_string(), "a=3,b=7".to_string())));
+        assert!(from_file.contains(&("num-agents".to_string(), "8".to_string())));
+        assert!(matches!(read_settings("liarslie.toml", "colour = 1"), Err(ConfigError::File(..))));
+        assert!(matches!(read_settings("liarslie.toml", "num-agents = "), Err(ConfigError::File(..))));
+
+        // The command line overrides the environment, which overrides the file, --value replaces the keys of the file
+        let env = |var : &str| match var {
+            "LIARSLIE_NUM_AGENTS" => Some("6".to_string()),
+            "LIARSLIE_LIAR_RATIO" => Some("0.5".to_string()),
+            "LIARSLIE_TUI" => Some("0".to_string()),
+            _ => None,
+        };
+        let words = |line : &str| line.split_whitespace().map(String::from).collect::<Vec<String>>();
+        let settings = layered(words("start --value 4 --liar-ratio 0.4"), "liarslie.toml", from_file.clone(), env).unwrap();
+        let source = |name : &str| settings.iter().find(|s| s.name == name).map(|s| (s.value.clone(), s.source.clone()));
+        assert_eq!(source("value"), Some(("4".to_string(), Source::CommandLine)));
+        assert_eq!(source("keys"), None);
+        assert_eq!(source("liar-ratio"), Some(("0.4".to_string(), Source::CommandLine)));
+        assert_eq!(source("num-agents"), Some(("6".to_string(), Source::Env("LIARSLIE_NUM_AGENTS".to_string()))));
+        assert_eq!(source("max-value"), Some(("10".to_string(), Source::File("liarslie.toml".to_string()))));
+        assert_eq!(source("tui"), Some(("false".to_string(), Source::Env("LIARSLIE_TUI".to_string()))));
+        assert_eq!(source("topology"), Some(("complete".to_string(), Source::Default)));
+
+        let config = Config::from_settings(settings).unwrap();
+        assert_eq!((config.value.as_str(), config.num_agents, config.tui), ("4", 6, false));
+        assert!(config.keys.is_empty());
+        let config = Config::from_settings(layered(words("start"), "liarslie.toml", from_file, |_| None).unwrap()).unwrap();
+        assert_eq!((config.keys.len(), config.num_agents, config.tui), (2, 8, true));
+        assert!(matches!(layered(words("start"), "liarslie.toml", Vec::new(), |_| Some("maybe".to_string())), Err(ConfigError::Env(..))));
+
+        // A port set in the environment or the file is only used by competitive games
+        let env = |var : &str| (var == "LIARSLIE_PORT").then(|| "9100".to_string());
+        let game = "start --value 4 --max-value 10 --num-agents 10 --liar-ratio 0.3";
+        let config = Config::from_settings(layered(words(game), "liarslie.toml", Vec::new(), env).unwrap()).unwrap();
+        assert_eq!((config.multiplayer, config.port), (None, 9000));
+        let config = Config::from_settings(layered(words(&format!("{} --multiplayer 2", game)), "liarslie.toml", Vec::new(), env).unwrap()).unwrap();
+        assert_eq!((config.multiplayer, config.port), (Some(2), 9100));
+        let from_file = vec![("port".to_string(), "9100".to_string())];
+        assert!(Config::from_settings(layered(words(game), "liarslie.toml", from_file, |_| None).unwrap()).is_ok());
+        assert!(matches!(Config::from_settings(layered(words(&format!("{} --port 9100", game)), "liarslie.toml", Vec::new(), |_| None).unwrap()), Err(ConfigError::Arguments(_))));
+    }
+}
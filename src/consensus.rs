@@ -0,0 +1,141 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
+use crate::value::NetworkValue;
+
+/*
+    Byzantine agreement among the agents, with the phase king protocol tolerating f < n / 3 liars.
+
+    The client sends "agree <Agreement>" to every agent. The agents then run f + 1 phases of three
+    synchronous rounds, exchanging "vote <Vote>" messages :
+        1. Every agent sends its value. An agent proposes the value received from at least n - f agents, if any.
+        2. Every agent sends its proposal. If a value is proposed by at least n - f agents, the agent
+           keeps it for sure (grade 2). If it is proposed by at least f + 1 agents, the agent takes it (grade 1).
+        3. The king of the phase sends its value. An agent which is not sure (grade < 2) takes the king's value.
+    At least one of the f + 1 kings tells the truth, after its phase every honest agent has the same
+    value and keeps it. When every honest agent starts with the same value, it is the agreed value.
+
+    Once done, every agent answers the client with its decided value, encoded like the answer to "talk".
+    Liars send a different random value to every agent at every round and answer their usual lie.
+*/
+
+pub const ROUND_TIMEOUT : Duration = Duration::from_millis(500);     // Time after which the missing votes of a round are ignored
+pub const ROUNDS_PER_PHASE : usize = 3;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Agreement{
+    pub session : u64,                  // Identifier of the agreement, chosen by the client
+    pub key : String,                   // Key to agree on, empty for a single value
+    pub peers : Vec<u16>,               // Ports of every agent taking part, the kings are chosen in this order
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(bound = "V : NetworkValue")]
+pub struct Vote<V : NetworkValue>{
+    pub session : u64,                  // Identifier of the agreement
+    pub round : usize,                  // Round of the protocol, ROUNDS_PER_PHASE per phase
+    pub from : u16,                     // Port of the sender
+    pub value : Option<V>,              // Value or proposal, None for no proposal
+}
+
+pub fn max_faulty(n : usize) -> usize {
+    /*
+        Returns the number of liars f tolerated among n agents, the largest f such that n > 3f.
+    */
+    n.saturating_sub(1) / 3
+}
+
+pub fn phases(n : usize) -> usize {
+    /*
+        Returns the number of phases among n agents, f + 1 so that at least one king is honest.
+    */
+    max_faulty(n) + 1
+}
+
+pub fn king(peers : &[u16], phase : usize) -> u16 {
+    /*
+        Returns the port of the king of phase.
+    */
+    peers[phase % peers.len()]
+}
+
+fn most_frequent<'a, V : NetworkValue>(votes : impl Iterator<Item = &'a V>) -> Option<(&'a V, usize)> {
+    /*
+        Returns the most frequent value and its count, the smallest value on ties.
+    */
+    let mut counts : HashMap<&V, usize> = HashMap::new();
+    for vote in votes {
+        *counts.entry(vote).or_insert(0) += 1;
+    }
+    counts.into_iter().max_by(|(v1, c1), (v2, c2)| c1.cmp(c2).then(v2.cmp(v1)))
+}
+
+pub fn propose<V : NetworkValue>(values : &[V], n : usize) -> Option<V> {
+    /*
+        First round : proposes the value received from at least n - f agents.
+
+        Args :
+            - values : values received during the round
+            - n : number of agents
+        Returns :
+            - Option<V> : proposal, None if no value is received often enough
+    */
+    let (value, count) = most_frequent(values.iter())?;
+    if count >= n - max_faulty(n) { Some(value.clone()) } else { None }
+}
+
+pub fn grade<V : NetworkValue>(proposals : &[Option<V>], n : usize) -> Option<(V, u8)> {
+    /*
+        Second round : grades the most proposed value.
+
+        Args :
+            - proposals : proposals received during the round
+            - n : number of agents
+        Returns :
+            - Option<(V, u8)> : (value, 2) if proposed by at least n - f agents, (value, 1) if proposed
+              by at least f + 1 agents, None otherwise
+    */
+    let f = max_faulty(n);
+    let (value, count) = most_frequent(proposals.iter().flatten())?;
+    if count >= n - f {
+        Some((value.clone(), 2))
+    } else if count > f {
+        Some((value.clone(), 1))
+    } else {
+        None
+    }
+}
+
+
+/*---------------------------- TESTS ----------------------------*/
+
+#[cfg(test)]
+mod tests {
+    use crate::client::Client;
+    use crate::consensus::{grade, max_faulty, propose};
+    use crate::network::Network;
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_consensus() {
+        assert_eq!(max_faulty(10), 3);
+        assert_eq!(max_faulty(3), 0);
+        assert_eq!(propose(&[1_u16, 1, 1, 1, 1, 1, 1, 2, 3, 4], 10), Some(1));
+        assert_eq!(propose(&[1_u16, 1, 1, 1, 1, 1, 2, 2, 3, 4], 10), None);
+        assert_eq!(grade(&[Some(1_u16), Some(1), Some(1), Some(1), None, None, None, Some(2), None, None], 10), Some((1, 1)));
+        assert_eq!(grade(&[Some(1_u16), Some(1), Some(1), None, None, None, None, Some(2), None, None], 10), None);
+
+        // With less than a third of liars, every honest agent decides the network value
+        let values = BTreeMap::from([(String::new(), 3_u16)]);
+        let network = Arc::new(Network::spawn(values, 10, 10, 0.3, 42).unwrap());
+        let client = {
+            let network = Arc::clone(&network);
+            Client::<u16>::new(0.3, move || network.ports())
+        };
+        let decisions = client.agree(&client.agents(), "");
+        assert_eq!(decisions.len(), 10);
+        assert_eq!(decisions.iter().filter(|r| r.value == 3).count(), 7);
+        network.shutdown();
+    }
+}
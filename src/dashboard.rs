@@ -0,0 +1,215 @@
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+use crate::client::{Response, Round};
+use crate::health::AgentHealth;
+use crate::value::NetworkValue;
+
+/*
+    Terminal dashboard redrawn in place with ANSI escape codes : the grid of agents (alive, dead or
+    slow, and their last answer), the histogram of the values answered during the round, the value
+    the client favors and the values already tried.
+*/
+
+pub const REFRESH_INTERVAL : Duration = Duration::from_millis(50);   // Minimum time between two redraws during a round
+const CELL_WIDTH : usize = 20;                                       // Width of an agent in the grid
+const LABEL_WIDTH : usize = 12;                                      // Values longer than this are cut
+
+const GREEN : &str = "\x1b[32m";
+const RED : &str = "\x1b[31m";
+const YELLOW : &str = "\x1b[33m";
+const BOLD : &str = "\x1b[1m";
+const RESET : &str = "\x1b[0m";
+
+fn label<V : NetworkValue>(value : &V) -> String {
+    /*
+        Returns the value as shown on the dashboard, cut to LABEL_WIDTH characters.
+    */
+    let label = value.to_string();
+    if label.chars().count() > LABEL_WIDTH {
+        format!("{}~", label.chars().take(LABEL_WIDTH - 1).collect::<String>())
+    } else {
+        label
+    }
+}
+
+pub struct Dashboard<V : NetworkValue>{
+    round : usize,                      // Round being played or last played, 0 before the first one
+    key : String,                       // Key of the round, empty for a single value
+    last : HashMap<u16, V>,             // Last answer of every agent (port => value)
+    histogram : BTreeMap<V, usize>,     // Number of agents which answered every value during the round
+    candidate : Option<V>,              // Value the client favors from the answers received so far
+    tried : Vec<V>,                     // Values already proposed for the key
+    width : usize,                      // Width of the terminal, from $COLUMNS, 80 by default
+    drawn : Option<Instant>,            // Time of the last redraw
+}
+
+impl<V : NetworkValue> Default for Dashboard<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<V : NetworkValue> Dashboard<V> {
+    pub fn new() -> Self {
+        Self {
+            round : 0,
+            key : String::new(),
+            last : HashMap::new(),
+            histogram : BTreeMap::new(),
+            candidate : None,
+            tried : Vec::new(),
+            width : env::var("COLUMNS").ok().and_then(|c| c.parse().ok()).unwrap_or(80),
+            drawn : None,
+        }
+    }
+
+    pub fn start_round(&mut self, round : usize, key : &str, tried : Vec<V>) {
+        /*
+            Clears the histogram before a round.
+        */
+        self.round = round;
+        self.key = key.to_string();
+        self.histogram.clear();
+        self.candidate = None;
+        self.tried = tried;
+    }
+
+    pub fn answer(&mut self, response : &Response<V>, candidate : Option<V>) {
+        /*
+            Adds an answer received during the round, see Client::play_with().
+        */
+        self.last.insert(response.agent, response.value.clone());
+        *self.histogram.entry(response.value.clone()).or_insert(0) += 1;
+        self.candidate = candidate;
+    }
+
+    pub fn end_round(&mut self, round : &Round<V>, tried : Vec<V>) {
+        /*
+            Shows the value proposed at the end of the round, it is now tried.
+        */
+        self.candidate = round.guess.clone();
+        self.tried = tried;
+    }
+
+    pub fn render(&self, agents : &[u16], health : &[Option<AgentHealth>]) -> String {
+        /*
+            Returns the dashboard as text.
+
+            Args :
+                - agents : ports the client queries, in the order of creation of the agents
+                - health : health of every agent in the same order, None if it was never pinged
+            Returns :
+                - String : the dashboard, with colors
+        */
+        let mut out = String::new();
+        let dead = health.iter().flatten().filter(|h| h.is_dead()).count();
+        let slow = health.iter().flatten().filter(|h| !h.is_dead() && h.is_slow()).count();
+        let round = match (self.round, self.key.is_empty()) {
+            (0, _) => "no round played".to_string(),
+            (round, true) => format!("round {}", round),
+            (round, false) => format!("round {} on {}", round, self.key),
+        };
+        let _ = writeln!(out, "{}liarslie{} - {} - {} agent(s) : {} alive, {} dead, {} slow", BOLD, RESET, round, agents.len(), agents.len() - dead, dead, slow);
+
+        // Grid of agents : index, state and last answer
+        let _ = writeln!(out, "\n{}Agents{}", BOLD, RESET);
+        let columns = (self.width / CELL_WIDTH).max(1);
+        for (row, chunk) in agents.chunks(columns).enumerate() {
+            for (column, port) in chunk.iter().enumerate() {
+                let index = row * columns + column;
+                let (color, state) = match health.get(index).and_then(|h| h.as_ref()) {
+                    Some(h) if h.is_dead() => (RED, "dead"),
+                    Some(h) if h.is_slow() => (YELLOW, "slow"),
+                    Some(_) => (GREEN, "up"),
+                    None => ("", "?"),
+                };
+                let answer = self.last.get(port).map(label).unwrap_or_else(|| "-".to_string());
+                let _ = write!(out, "{:>4} {}{:<4}{} {:<width$}", index, color, state, RESET, answer, width = CELL_WIDTH - 10);
+            }
+            out.push('\n');
+        }
+
+        // Histogram of the answers of the round
+        let answered : usize = self.histogram.values().sum();
+        let _ = writeln!(out, "\n{}Answers{} ({} / {})", BOLD, RESET, answered, agents.len());
+        let most = self.histogram.values().copied().max().unwrap_or(0);
+        let bar_width = self.width.saturating_sub(LABEL_WIDTH + 30).max(10);
+        for (value, count) in &self.histogram {
+            let bar = "#".repeat((count * bar_width).div_ceil(most.max(1)));
+            let note = if self.candidate.as_ref() == Some(value) {
+                format!(" {}<= candidate{}", GREEN, RESET)
+            } else if self.tried.contains(value) {
+                " (tried)".to_string()
+            } else {
+                String::new()
+            };
+            let _ = writeln!(out, "{:>width$} {:<bar_width$} {:>4}{}", label(value), bar, count, note, width = LABEL_WIDTH);
+        }
+        if let Some(candidate) = &self.candidate {
+            let _ = writeln!(out, "Candidate : {}", label(candidate));
+        }
+        let tried : Vec<String> = self.tried.iter().map(label).collect();
+        let _ = writeln!(out, "Already tried : {}", if tried.is_empty() { "none".to_string() } else { tried.join(", ") });
+        out
+    }
+
+    pub fn draw(&mut self, agents : &[u16], health : &[Option<AgentHealth>]) {
+        /*
+            Clears the terminal and draws the dashboard.
+        */
+        print!("\x1b[2J\x1b[H{}", self.render(agents, health));
+        io::stdout().flush().expect("Couldn't flush stdout");
+        self.drawn = Some(Instant::now());
+    }
+
+    pub fn refresh(&mut self, agents : &[u16], health : &[Option<AgentHealth>]) {
+        /*
+            Draws the dashboard unless it was drawn less than REFRESH_INTERVAL ago.
+        */
+        if self.drawn.is_none_or(|drawn| drawn.elapsed() >= REFRESH_INTERVAL) {
+            self.draw(agents, health);
+        }
+    }
+}
+
+
+/*---------------------------- TESTS ----------------------------*/
+
+#[cfg(test)]
+mod tests {
+    use crate::client::{Response, Round};
+    use crate::dashboard::Dashboard;
+    use crate::health::AgentHealth;
+
+    #[test]
+    fn test_dashboard() {
+        let mut dashboard : Dashboard<u16> = Dashboard::new();
+        let agents = [5000, 5001, 5002];
+        let dead = AgentHealth { failures : 2, ..Default::default() };
+        let health = [Some(AgentHealth::default()), Some(dead), None];
+        assert!(dashboard.render(&agents, &health).contains("no round played"));
+
+        dashboard.start_round(2, "", vec![8]);
+        dashboard.answer(&Response { agent : 5000, value : 3, epoch : 0 }, Some(3));
+        dashboard.answer(&Response { agent : 5001, value : 8, epoch : 0 }, Some(3));
+        let screen = dashboard.render(&agents, &health);
+        assert!(screen.contains("round 2"));
+        assert!(screen.contains("2 alive, 1 dead"));
+        assert!(screen.contains("(2 / 3)"));
+        assert!(screen.contains("<= candidate"));
+        assert!(screen.contains("(tried)"));
+        assert!(screen.contains("Already tried : 8"));
+
+        // The last answer of every agent stays in the grid once the round is over
+        let round = Round { round : 2, key : String::new(), agents : 3, responses : Vec::new(), guess : Some(3), epoch : 0 };
+        dashboard.end_round(&round, vec![3, 8]);
+        dashboard.start_round(3, "", vec![3, 8]);
+        let screen = dashboard.render(&agents, &health);
+        assert!(screen.contains("(0 / 3)"));
+        assert!(screen.contains("Already tried : 3, 8"));
+        assert!(screen.lines().nth(3).unwrap().contains(" 3 "));
+    }
+}
@@ -0,0 +1,175 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::net::{Shutdown, SocketAddr, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use log::{debug, info, warn};
+use crate::network::Network;
+use crate::value::NetworkValue;
+
+/*
+    Network value changing over time. At every tick, the source draws a new value for every key,
+    increments its epoch and pushes "update <Update>" to the agents in turn over the first half of
+    the interval : until it reaches them, agents answer the value of the previous epoch, i.e. stale
+    reads. Agents restarted by the supervisor come back with the value of epoch 0.
+*/
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(bound = "V : NetworkValue")]
+pub struct Update<V : NetworkValue>{
+    pub epoch : u64,                    // Epoch of the value, the initial value is epoch 0
+    pub key : String,                   // Key updated, empty for a single value
+    pub value : V,                      // New network value of the key
+}
+
+fn push<V : NetworkValue>(port : u16, update : &Update<V>) {
+    /*
+        Sends "update <Update>" to the agent listening on port.
+    */
+    let msg = format!("update {}", serde_json::to_string(update).expect("An update is serializable"));
+    let sent = TcpStream::connect(SocketAddr::from(([127, 0, 0, 1], port))).and_then(|mut stream| {
+        stream.write_all(msg.as_bytes())?;
+        stream.shutdown(Shutdown::Write)
+    });
+    if let Err(e) = sent {
+        debug!("Failed to send update to {} : {}", port, e);
+    }
+}
+
+pub struct Source<V : NetworkValue>{
+    values : Arc<Mutex<BTreeMap<String, Vec<V>>>>,  // Value of every key at every epoch, the last one is current
+    running : Arc<AtomicBool>,
+    thread : Mutex<Option<JoinHandle<()>>>,
+}
+
+impl<V : NetworkValue> Source<V> {
+    pub fn start(network : Arc<Network<V>>, values : BTreeMap<String, V>, max_value : V, interval : Duration, seed : u64) -> Self {
+        /*
+            Launches the thread pushing a new value of every key every interval.
+
+            Args :
+                - network : network of agents
+                - values : value of every key at epoch 0
+                - max_value : maximum value, the new values are drawn in [1 ; max_value]
+                - interval : time between two epochs
+                - seed : seed of the new values and of the order of the agents
+            Returns :
+                - Source
+        */
+        let history : BTreeMap<String, Vec<V>> = values.into_iter().map(|(key, value)| (key, vec![value])).collect();
+        let values = Arc::new(Mutex::new(history));
+        let running = Arc::new(AtomicBool::new(true));
+        let thread = {
+            let values = Arc::clone(&values);
+            let running = Arc::clone(&running);
+            thread::spawn(move || {
+                let mut rng = StdRng::seed_from_u64(seed);
+                loop {
+                    let start = Instant::now();
+                    while running.load(Ordering::SeqCst) && start.elapsed() < interval {
+                        thread::sleep(Duration::from_millis(1));
+                    }
+                    if !running.load(Ordering::SeqCst) {
+                        break;
+                    }
+
+                    // The new value of every key is current as soon as it is drawn
+                    let updates : Vec<Update<V>> = values.lock().expect("Source lock poisoned").iter_mut().map(|(key, history)| {
+                        let value = history.last().expect("Every key has a value").lie(&max_value, &mut rng);
+                        history.push(value.clone());
+                        Update { epoch : history.len() as u64 - 1, key : key.clone(), value }
+                    }).collect();
+                    info!("Source: epoch {}", updates.first().map(|u| u.epoch).unwrap_or(0));
+
+                    let mut ports = network.ports();
+                    ports.shuffle(&mut rng);
+                    let gap = interval / 2 / ports.len().max(1) as u32;
+                    for port in ports {
+                        for update in &updates {
+                            push(port, update);
+                        }
+                        thread::sleep(gap);
+                    }
+                }
+            })
+        };
+        Self { values, running, thread : Mutex::new(Some(thread)) }
+    }
+
+    pub fn current(&self, key : &str) -> Option<(u64, V)> {
+        /*
+            Returns the current epoch of key and its value.
+        */
+        let values = self.values.lock().expect("Source lock poisoned");
+        let history = values.get(key)?;
+        history.last().map(|value| (history.len() as u64 - 1, value.clone()))
+    }
+
+    pub fn value_at(&self, key : &str, epoch : u64) -> Option<V> {
+        /*
+            Returns the value of key at epoch, None if the epoch is not reached yet.
+        */
+        self.values.lock().expect("Source lock poisoned").get(key)?.get(epoch as usize).cloned()
+    }
+
+    pub fn stop(&self) {
+        /*
+            Stops pushing values and joins the thread, once the current epoch is pushed to every agent.
+        */
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(thread) = self.thread.lock().expect("Source lock poisoned").take() {
+            if thread.join().is_err() {
+                warn!("The source thread has panicked");
+            }
+        }
+    }
+}
+
+
+/*---------------------------- TESTS ----------------------------*/
+
+#[cfg(test)]
+mod tests {
+    use crate::client::Client;
+    use crate::epoch::Source;
+    use crate::network::Network;
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_epoch() {
+        let values = BTreeMap::from([(String::new(), 3_u16)]);
+        let network = Arc::new(Network::spawn(values.clone(), 1000, 10, 0.3, 42).unwrap());
+        let source = Source::start(Arc::clone(&network), values, 1000, Duration::from_millis(50), 42);
+        assert_eq!(source.current(""), Some((0, 3)));
+        thread::sleep(Duration::from_millis(200));
+        let (epoch, value) = source.current("").unwrap();
+        assert!(epoch >= 2);
+        assert_eq!(source.value_at("", 0), Some(3));
+        assert_eq!(source.value_at("", epoch), Some(value));
+        assert_eq!(source.value_at("", epoch + 1), None);
+
+        // The client reads the value of the newest epoch answered
+        let mut client = {
+            let network = Arc::clone(&network);
+            Client::<u16>::new(0.3, move || network.ports())
+        };
+        client.set_epochs(true);
+        source.stop();
+        let (epoch, value) = source.current("").unwrap();
+        thread::sleep(Duration::from_millis(20));
+        let round = client.play("").unwrap();
+        assert_eq!(round.epoch, epoch);
+        assert!(round.responses.iter().all(|r| r.epoch == epoch));
+        assert_eq!(round.guess, Some(value));
+        network.shutdown();
+    }
+}
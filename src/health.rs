@@ -0,0 +1,212 @@
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub const PING_INTERVAL : Duration = Duration::from_millis(1000);    // Time between two pings of an agent
+pub const PING_TIMEOUT : Duration = Duration::from_millis(500);      // Time after which a ping fails
+pub const SLOW_THRESHOLD : Duration = Duration::from_millis(100);    // Round trip time above which an agent is slow
+pub const DEAD_AFTER : u32 = 2;                                      // Failed pings in a row after which an agent is dead
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pong{
+    pub id : u16,                       // Identifier the agent answered with
+    pub uptime : Duration,              // Time since the agent was created
+    pub rtt : Duration,                 // Round trip time of the ping
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AgentHealth{
+    pub last_pong : Option<Pong>,       // Last successful ping
+    pub last_seen : Option<Instant>,    // Time of the last successful ping
+    pub failures : u32,                 // Failed pings in a row
+}
+
+impl AgentHealth{
+    pub fn is_dead(&self) -> bool {
+        self.failures >= DEAD_AFTER
+    }
+
+    pub fn is_slow(&self) -> bool {
+        self.last_pong.as_ref().is_some_and(|pong| pong.rtt > SLOW_THRESHOLD)
+    }
+}
+
+pub fn ping(port : u16, timeout : Duration) -> io::Result<Pong> {
+    /*
+        Sends "ping" to the agent listening on port and parses its answer "pong <id> <uptime in ms>".
+
+        Args :
+            - port : port of the agent
+            - timeout : maximum time to connect and to wait for the answer
+        Returns :
+            - Pong : answer of the agent, or an error if it did not answer correctly in time
+    */
+    let start = Instant::now();
+    let addrs = SocketAddr::from(([127, 0, 0, 1], port));
+    let mut stream = TcpStream::connect_timeout(&addrs, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.write_all(b"ping")?;
+
+    // The agent closes the connection after answering
+    let mut answer = String::new();
+    stream.read_to_string(&mut answer)?;
+    let rtt = start.elapsed();
+
+    let invalid = || io::Error::new(io::ErrorKind::InvalidData, format!("invalid answer to ping : {}", answer));
+    let words : Vec<&str> = answer.split(' ').collect();
+    match words[..] {
+        ["pong", id, uptime] => Ok(Pong {
+            id : id.parse().map_err(|_| invalid())?,
+            uptime : Duration::from_millis(uptime.parse().map_err(|_| invalid())?),
+            rtt,
+        }),
+        _ => Err(invalid()),
+    }
+}
+
+pub struct Monitor{
+    statuses : Arc<Mutex<HashMap<u16, AgentHealth>>>,  // Health of every agent (port => health)
+    running : Arc<AtomicBool>,                          // Cleared to stop the thread
+    thread : Option<thread::JoinHandle<()>>,
+}
+
+impl Monitor{
+    pub fn start<F>(ports : F, interval : Duration) -> Self
+    where F : Fn() -> Vec<u16> + Send + 'static {
+        /*
+            Starts a thread pinging every agent each interval.
+
+            Args :
+                - ports : returns the ports of the agents to ping, called before every round of pings
+                - interval : time between two rounds of pings
+            Returns :
+                - Monitor
+        */
+        let statuses : Arc<Mutex<HashMap<u16, AgentHealth>>> = Arc::new(Mutex::new(HashMap::new()));
+        let running = Arc::new(AtomicBool::new(true));
+
+        let thread = {
+            let statuses = Arc::clone(&statuses);
+            let running = Arc::clone(&running);
+            thread::spawn(move || {
+                while running.load(Ordering::SeqCst) {
+                    let ports = ports();
+                    let results : Vec<(u16, io::Result<Pong>)> = ports.iter().map(|&port| (port, ping(port, PING_TIMEOUT))).collect();
+
+                    let mut statuses = statuses.lock().expect("Health lock poisoned");
+                    // Forget the agents which are not in the registry anymore
+                    statuses.retain(|port, _| ports.contains(port));
+                    for (port, result) in results {
+                        let health = statuses.entry(port).or_default();
+                        match result {
+                            Ok(pong) => {
+                                health.last_pong = Some(pong);
+                                health.last_seen = Some(Instant::now());
+                                health.failures = 0;
+                            },
+                            Err(_) => health.failures += 1,
+                        }
+                    }
+                    drop(statuses);
+
+                    // Sleep by small steps to stop quickly
+                    let start = Instant::now();
+                    while running.load(Ordering::SeqCst) && start.elapsed() < interval {
+                        thread::sleep(Duration::from_millis(10).min(interval));
+                    }
+                }
+            })
+        };
+
+        Self {
+            statuses,
+            running,
+            thread : Some(thread),
+        }
+    }
+
+    pub fn report(&self) -> Vec<(u16, AgentHealth)> {
+        /*
+            Returns the health of every monitored agent sorted by port.
+        */
+        let statuses = self.statuses.lock().expect("Health lock poisoned");
+        let mut report : Vec<(u16, AgentHealth)> = statuses.iter().map(|(port, health)| (*port, health.clone())).collect();
+        report.sort_by_key(|(port, _)| *port);
+        report
+    }
+
+    pub fn print_report(&self) {
+        /*
+            Prints the dead and slow agents, for the "health" command.
+        */
+        let report = self.report();
+        let mut dead = 0;
+        let mut slow = 0;
+        for (port, health) in &report {
+            if health.is_dead() {
+                dead += 1;
+                match health.last_seen {
+                    Some(seen) => println!("Agent {} : dead, {} failed ping(s), last seen {:.1}s ago", port, health.failures, seen.elapsed().as_secs_f32()),
+                    None => println!("Agent {} : dead, {} failed ping(s), never answered", port, health.failures),
+                }
+            } else if health.is_slow() {
+                slow += 1;
+                let pong = health.last_pong.as_ref().expect("A slow agent has answered");
+                println!("Agent {} : slow, answered ping in {} ms", port, pong.rtt.as_millis());
+            }
+        }
+        println!("{} agent(s) monitored : {} alive, {} dead, {} slow", report.len(), report.len() - dead, dead, slow);
+    }
+
+    pub fn stop(mut self) {
+        /*
+            Stops the monitoring thread and waits for it.
+        */
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+
+/*---------------------------- TESTS ----------------------------*/
+
+#[cfg(test)]
+mod tests {
+    use crate::agent::Agent;
+    use crate::health::{Monitor, PING_TIMEOUT, ping};
+    use std::io::Write;
+    use std::net::{SocketAddr, TcpStream};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_health() {
+        let mut agent = Agent::new(vec![(String::new(), 1_u16, false)], &2, 0);
+        let port = agent.id;
+        let thread = thread::spawn(move || {agent.run();});
+
+        let pong = ping(port, PING_TIMEOUT).unwrap();
+        assert_eq!(pong.id, port);
+
+        let monitor = Monitor::start(move || vec![port], Duration::from_millis(20));
+        thread::sleep(Duration::from_millis(100));
+        let report = monitor.report();
+        assert_eq!(report.len(), 1);
+        assert!(!report[0].1.is_dead());
+        assert!(report[0].1.last_pong.is_some());
+
+        // Once stopped, the agent is reported dead
+        TcpStream::connect(SocketAddr::from(([127, 0, 0, 1], port))).unwrap().write_all(b"stop").unwrap();
+        thread.join().expect("The thread being joined has panicked");
+        thread::sleep(Duration::from_millis(200));
+        assert!(monitor.report()[0].1.is_dead());
+
+        monitor.stop();
+    }
+}
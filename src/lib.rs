@@ -0,0 +1,39 @@
+/*
+    liarslie : a network of agents agreeing on a value, some of them lying about it, and a client
+    guessing the value from their answers.
+
+    Network spawns the agents, Client plays rounds against them and guesses the network value :
+
+        let values = BTreeMap::from([(String::new(), 3_u16)]);
+        let network = Arc::new(Network::spawn(values, 10, 10, 0.3, 42)?);
+        let agents = Arc::clone(&network);
+        let mut client = Client::new(0.3, move || agents.ports());
+        let guess = client.play("").and_then(|round| round.guess.clone());
+        network.shutdown();
+*/
+
+pub mod agent;
+pub mod api;
+pub mod chaos;
+pub mod client;
+pub mod config;
+pub mod consensus;
+pub mod dashboard;
+pub mod epoch;
+pub mod health;
+pub mod logger;
+pub mod multiplayer;
+pub mod network;
+pub mod proxy;
+pub mod reputation;
+pub mod score;
+mod supervisor;
+pub mod topology;
+pub mod transcript;
+pub mod turncoat;
+pub mod value;
+
+pub use agent::Agent;
+pub use client::{Client, Response, Round};
+pub use network::Network;
+pub use value::{NetworkValue, ValueType};
@@ -0,0 +1,181 @@
+use log::{LevelFilter, Log, Metadata, Record};
+use std::env;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const AGENT_TARGET : &str = "liarslie::agent";
+
+pub struct Logger{
+    default : LevelFilter,                      // Level used when no directive matches
+    directives : Vec<(String, LevelFilter)>,    // Per-module levels (module path, level)
+    json : bool,                                // One JSON object per line instead of plain text
+    agent_file : Option<Mutex<File>>,          // If set, agent logs are written here instead of stderr
+}
+
+pub fn parse_filters(spec : &str) -> (LevelFilter, Vec<(String, LevelFilter)>) {
+    /*
+        Parses a filter specification of the form :
+            warn,agent=debug,liarslie::config=off
+        A bare level sets the default level, "module=level" sets the level of a module and its children.
+        Module names may omit the "liarslie::" prefix. Invalid items are ignored.
+
+        Args :
+            - spec : filter specification
+        Returns :
+            - LevelFilter : default level (info if not given)
+            - Vec<(String, LevelFilter)> : per-module levels
+    */
+    let mut default = LevelFilter::Info;
+    let mut directives = Vec::new();
+
+    for item in spec.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()) {
+        match item.split_once('=') {
+            None => {
+                if let Ok(level) = item.parse::<LevelFilter>() {
+                    default = level;
+                }
+            },
+            Some((module, level)) => {
+                if let Ok(level) = level.trim().parse::<LevelFilter>() {
+                    let module = module.trim();
+                    let module = if module == "liarslie" || module.starts_with("liarslie::") {
+                        module.to_string()
+                    } else {
+                        format!("liarslie::{}", module)
+                    };
+                    directives.push((module, level));
+                }
+            },
+        }
+    }
+    (default, directives)
+}
+
+impl Logger{
+    pub fn from_env() -> Self {
+        /*
+            Builds the logger from the environment :
+                - LIARSLIE_LOG : filter specification (see parse_filters), default "info"
+                - LIARSLIE_LOG_FORMAT : "json" for JSON lines, anything else for plain text
+                - LIARSLIE_AGENT_LOG : file receiving the agents' logs instead of stderr
+        */
+        let (default, directives) = parse_filters(&env::var("LIARSLIE_LOG").unwrap_or_default());
+        let json = env::var("LIARSLIE_LOG_FORMAT").map(|f| f.eq_ignore_ascii_case("json")).unwrap_or(false);
+
+        let agent_file = match env::var("LIARSLIE_AGENT_LOG") {
+            Ok(path) => match OpenOptions::new().create(true).append(true).open(&path) {
+                Ok(file) => Some(Mutex::new(file)),
+                Err(e) => {
+                    eprintln!("Unable to open agent log file {} : {}", path, e);
+                    None
+                }
+            },
+            Err(_) => None,
+        };
+
+        Self {
+            default,
+            directives,
+            json,
+            agent_file,
+        }
+    }
+
+    fn level_for(&self, target : &str) -> LevelFilter {
+        /*
+            Returns the level of the most specific directive matching target, or the default level.
+        */
+        self.directives.iter()
+            .filter(|(module, _)| target == module || target.starts_with(&format!("{}::", module)))
+            .max_by_key(|(module, _)| module.len())
+            .map(|(_, level)| *level)
+            .unwrap_or(self.default)
+    }
+
+    fn max_level(&self) -> LevelFilter {
+        self.directives.iter().map(|(_, level)| *level).fold(self.default, Ord::max)
+    }
+
+    fn format(&self, record : &Record) -> String {
+        if self.json {
+            let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis()).unwrap_or(0);
+            serde_json::json!({
+                "timestamp_ms" : timestamp as u64,
+                "level" : record.level().as_str(),
+                "target" : record.target(),
+                "message" : record.args().to_string(),
+            }).to_string()
+        } else {
+            format!("[{} {}] {}", record.level(), record.target(), record.args())
+        }
+    }
+}
+
+impl Log for Logger{
+    fn enabled(&self, metadata : &Metadata) -> bool {
+        metadata.level() <= self.level_for(metadata.target())
+    }
+
+    fn log(&self, record : &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let line = self.format(record);
+
+        // Agent logs go to the agent file when one is configured, everything else to stderr
+        if let Some(file) = &self.agent_file {
+            if record.target().starts_with(AGENT_TARGET) {
+                if let Ok(mut file) = file.lock() {
+                    let _ = writeln!(file, "{}", line);
+                }
+                return;
+            }
+        }
+        let _ = writeln!(io::stderr(), "{}", line);
+    }
+
+    fn flush(&self) {
+        if let Some(file) = &self.agent_file {
+            if let Ok(mut file) = file.lock() {
+                let _ = file.flush();
+            }
+        }
+    }
+}
+
+pub fn init(){
+    /*
+        Installs the logger built from the environment as the global logger.
+        Game prompts are still printed on stdout, logs are written on stderr.
+    */
+    let logger = Logger::from_env();
+    log::set_max_level(logger.max_level());
+    if log::set_boxed_logger(Box::new(logger)).is_err() {
+        eprintln!("A logger was already installed");
+    }
+}
+
+
+/*---------------------------- TESTS ----------------------------*/
+
+#[cfg(test)]
+mod tests {
+    use crate::logger::parse_filters;
+    use log::LevelFilter;
+
+    #[test]
+    fn test_parse_filters() {
+        let (default, directives) = parse_filters("warn, agent=debug,liarslie::config=off,bad=level,nonsense");
+        assert_eq!(default, LevelFilter::Warn);
+        assert_eq!(directives, vec![
+            ("liarslie::agent".to_string(), LevelFilter::Debug),
+            ("liarslie::config".to_string(), LevelFilter::Off),
+        ]);
+
+        let (default, directives) = parse_filters("");
+        assert_eq!(default, LevelFilter::Info);
+        assert!(directives.is_empty());
+    }
+}
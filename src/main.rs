@@ -1,240 +1,796 @@
-mod agent;
-mod config;
-
-use agent::Agent;
-use config::{Config, write_config_file, read_config_file, delete_config_file};
-
-use std::collections::{HashMap, HashSet};
+use liarslie::api::Server;
+use liarslie::chaos::{Chaos, summary};
+use liarslie::{Client, Network, NetworkValue, Response, ValueType, logger};
+use liarslie::network::Options;
+use liarslie::consensus::max_faulty;
+use liarslie::dashboard::Dashboard;
+use liarslie::epoch::Source;
+use liarslie::config::{Config, ConfigError, settings, settings_file, show, write_config_file, delete_config_file};
+use liarslie::multiplayer::{GameServer, TURN_TIMEOUT, bot};
+use liarslie::health::{AgentHealth, Monitor, PING_INTERVAL};
+use liarslie::proxy::Proxy;
+use liarslie::reputation::{self, merge, penalties, reputation_file, roster};
+use liarslie::score::{Difficulty, Score, print_leaderboard, save, scores_file};
+use liarslie::transcript::{Entry, Recorder, read_transcript, read_value_type};
+use liarslie::value::parse_and_check;
+
+use std::collections::{BTreeMap, HashSet};
+use std::process;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpStream};
 use std::thread;
-use std::io::{self, Read, Write};
-use std::net::{TcpStream, SocketAddr};
-use std::str::{from_utf8};
-
+use std::time::Instant;
+use log::error;
 
+// Port of the HTTP API when "serve" is given no port
+const DEFAULT_API_PORT : u16 = 8080;
 
-fn init(config : Config) -> (Vec<thread::JoinHandle<()>>, Vec<u16>) { 
-    /* 
-        Reads config file and launches a total of config.num_agents agents' threads among which
-        config.liar_ratio * config.num_agents are liars and the rest are telling the truth.
+// Set once the agents are being stopped, by the "stop" command or by a signal
+static TEARDOWN_STARTED : AtomicBool = AtomicBool::new(false);
 
-        Returns a vector of threads to join and a vector of port numbers chosen randomly by each agent.
+// Set by the first signal, a second one exits immediately
+static INTERRUPTED : AtomicBool = AtomicBool::new(false);
 
-        Args :
-            - config : Config structure 
-        Returns :
-            -  Vec<thread::JoinHandle<()>> : Threads to join with shutdown()
-            -  Vec<u16> : Port numbers.
+fn keys_list<V>(values : &BTreeMap<String, V>) -> String {
+    /*
+        Returns the keys of a key-value network separated by commas.
     */
-    let number_of_liars = (config.liar_ratio * (config.num_agents as f32)) as u16;
-    
-    let mut thread_vec : Vec<thread::JoinHandle<()>> = Vec::new();
-    let mut port_vec : Vec<u16> = Vec::new();
-
-    // Launch the liars
-    for _ in 0..number_of_liars {
-        let mut agent = Agent::new(config.value, config.max_value, true);
-        let id = agent.id;
-        port_vec.push(id);
-
-        let thread = thread::spawn(move || {agent.run();});
-        thread_vec.push(thread);
-    }
-
-    // Launch the real agents
-    for _ in 0..config.num_agents - number_of_liars {
-        let mut agent = Agent::new(config.value, config.max_value, false);
-        let id = agent.id;
-        port_vec.push(id);
+    values.keys().cloned().collect::<Vec<String>>().join(", ")
+}
 
-        let thread = thread::spawn(move || {agent.run();});
-        thread_vec.push(thread);
-    }
+fn agent_health<V : NetworkValue>(monitor : &Monitor, network : &Network<V>) -> Vec<Option<AgentHealth>> {
+    /*
+        Returns the health of every agent in their order of creation, None if it was never pinged.
+    */
+    let report : BTreeMap<u16, AgentHealth> = monitor.report().into_iter().collect();
+    network.ports().iter().map(|port| report.get(port).cloned()).collect()
+}
 
-    (thread_vec, port_vec)
+struct Game<'a, V : NetworkValue>{
+    recorder : Option<Recorder>,        // Transcript in which every round is recorded, if any
+    monitor : &'a Monitor,              // Agents' health monitor
+    network : &'a Network<V>,           // Network of agents
+    proxy : Option<&'a Proxy>,          // Proxy between the client and the agents, if any
+    chaos : Option<Chaos>,              // Churn applied to the agents during every round, if any
+    dashboard : Option<Dashboard<V>>,   // Dashboard redrawn during every round, if any
+    source : Option<&'a Source<V>>,     // Source pushing a new value every epoch, the values are fixed if None
+    config : &'a Config,                // Settings of the game, the score is saved under config.player
 }
 
-fn game_loop(value : u16, liar_ratio : f32){
+fn game_loop<V : NetworkValue>(values : BTreeMap<String, V>, client : &mut Client<V>, game : &mut Game<V>){
     /* 
-        Reads the stdin until the end of the game. It waits for a "play" or "stop" command. 
-        If it reads play, it plays a round of the game. If the game is won, the program stops. 
-        If it reads stop, it stops the game.
+        Reads the stdin until the end of the game. It waits for a "play", "agree", "health", "reputation", "net", "leaderboard", "config show" or "stop" command. 
+        If it reads play, the client plays a round of the game. If the game is won, the score is saved and the program stops. 
+        With a round limit or a query budget, the budget left is shown after every round and the game is lost once it is spent.
+        In chaos mode, agents are stopped, restarted and frozen during the round, which is then summed up.
+        With a dashboard, the agents and the answers are shown live during the round.
+        With epochs, the value proposed must be the current value of the source, read at its current epoch.
+        If it reads agree, the agents run a Byzantine agreement among themselves.
+        If it reads health, it lists the dead, slow and restarted agents.
+        If it reads reputation, it lists the reputation of every agent (see client.rs).
+        If it reads net, it shows or changes the faults injected by the proxy (see proxy.rs).
+        If it reads leaderboard, it shows the best scores (see score.rs).
+        If it reads config show, it shows the settings of the game and where each one came from.
+        If it reads stop or the end of stdin, it stops the game.
+        For a key-value network, it waits for "play <key>" and the game is won once the value of
+        every key is found.
 
         Args : 
-            - value : target value
-            - liar_ratio : liar ratio
+            - values : target value of every key, the empty key for a single value
+            - client : client of the game
+            - game : recorder, monitor, network, proxy, chaos, dashboard, source and settings of the game
     */
+    let Game { recorder, monitor, network, proxy, chaos, dashboard, source, config } = game;
+    let (monitor, network, proxy, source, config) = (*monitor, *network, *proxy, *source, *config);
     
-    let mut already_tried : HashSet<u16> = HashSet::new();  // Set of values proposed in the previous rounds
-    let mut number_of_rounds = 1;
+    let mut found : HashSet<String> = HashSet::new();                       // Keys whose value is found
+    if let Some(dashboard) = dashboard.as_mut() {
+        dashboard.draw(&client.agents(), &agent_health(monitor, network));
+    }
+    let started = Instant::now();
     println!("ready");                                  
     loop {
 
         let mut input = String::new();
         io::stdout().flush().expect("Couldn't flush stdout");
-        io::stdin().read_line(&mut input).expect("Error reading input.");
-        match input.trim(){
-            "play" => {
-                if play(&mut already_tried, liar_ratio, value){
-                    println!("You have found the correct value after {} round(s) !", number_of_rounds);
+        if io::stdin().read_line(&mut input).expect("Error reading input.") == 0 {
+            break;
+        }
+        let words : Vec<&str> = input.split_whitespace().collect();
+        match words[..] {
+            ["play"] | ["play", _] => {
+                let key = words.get(1).copied().unwrap_or("");
+                let value = match values.get(key) {
+                    Some(value) => value,
+                    None => {
+                        println!("You should enter 'play <key>' with one of the keys {}, you entered {}", keys_list(&values), input);
+                        continue;
+                    }
+                };
+                // Nothing is left to find for this key
+                if found.contains(key) {
+                    let left : Vec<&str> = values.keys().filter(|k| !found.contains(*k)).map(|k| k.as_str()).collect();
+                    println!("You have already found the value of {}, play one of the keys {} left", key, left.join(", "));
+                    continue;
+                }
+                let agents = client.agents();
+                if let Some(dashboard) = dashboard.as_mut() {
+                    dashboard.start_round(client.rounds() + 1, key, client.tried(key));
+                    dashboard.draw(&agents, &agent_health(monitor, network));
+                }
+                let mut play = || client.play_with(key, |response, candidate| {
+                    if let Some(dashboard) = dashboard.as_mut() {
+                        dashboard.answer(response, candidate);
+                        dashboard.refresh(&agents, &agent_health(monitor, network));
+                    }
+                }).map(|round| round.round);
+                let (number, events) = match chaos.as_mut() {
+                    Some(chaos) => {
+                        let (number, events) = chaos.during(network, play);
+                        (number, Some(events))
+                    },
+                    None => (play(), None),
+                };
+                let number = match number {
+                    Some(number) => number,
+                    None => {
+                        println!("The budget is spent, no round is left");
+                        break;
+                    }
+                };
+                let round = &client.history()[number - 1];
+                if let Some(dashboard) = dashboard.as_mut() {
+                    dashboard.end_round(round, client.tried(key));
+                    dashboard.draw(&client.agents(), &agent_health(monitor, network));
+                }
+                if let Some(events) = events {
+                    println!("Chaos during round {} : {}", number, summary(&events));
+                }
+                match &round.guess {
+                    Some(guess) if source.is_some() => println!("You propose value {} read at epoch {}", guess, round.epoch),
+                    Some(guess) => println!("You propose value {}", guess),
+                    None if round.responses.is_empty() => println!("No agent answered, nothing to propose"),
+                    None => println!("Every answered value has already been tried, nothing to propose"),
+                }
+                let won = match source.and_then(|source| source.current(key)) {
+                    Some((epoch, current)) => {
+                        let won = round.guess.as_ref() == Some(&current) && round.epoch == epoch;
+                        if !won && round.epoch < epoch {
+                            let was = match (&round.guess, source.and_then(|source| source.value_at(key, round.epoch))) {
+                                (Some(guess), Some(old)) if *guess == old => format!(", {} was the value of epoch {}", guess, round.epoch),
+                                _ => String::new(),
+                            };
+                            println!("Stale read : the network value is at epoch {}{}", epoch, was);
+                        }
+                        won
+                    },
+                    None => round.guess.as_ref() == Some(value),
+                };
+                if let Some(recorder) = recorder.as_mut() {
+                    let entry = Entry::Round {
+                        round : round.round,
+                        key : round.key.clone(),
+                        agents : round.agents,
+                        responses : round.responses.clone(),
+                        guess : round.guess.clone(),
+                        identities : round.responses.iter()
+                            .filter_map(|r| client.identity(r.agent).map(|identity| (r.agent, identity.to_string())))
+                            .collect(),
+                    };
+                    if let Err(e) = recorder.record(&entry) {
+                        error!("Failed to record round {} : {}", round.round, e);
+                    }
+                }
+                if won {
+                    client.set_found(key);
+                    found.insert(key.to_string());
+                    if found.len() == values.len() {
+                        println!("You have found the correct value after {} round(s) !", client.rounds());
+                        let score = Score::new(&config.player, client.liar_ratio, client.history(), started.elapsed());
+                        println!("Score of {} ({}) : {} round(s), {} agent(s) queried, {:.1} s", score.player, score.difficulty, score.rounds, score.queries, score.millis as f64 / 1000.);
+                        if let Err(e) = save(&scores_file(), &score) {
+                            error!("Unable to save the score in {} : {}", scores_file(), e);
+                        }
+                        break;
+                    }
+                    println!("You have found the value of {} after {} round(s), {} key(s) left", key, client.rounds(), values.len() - found.len());
+                }
+                if let Some(left) = budget_left(client) {
+                    println!("Budget left : {}", left);
+                }
+                if client.out_of_budget() {
+                    println!("You have lost : the budget is spent after {} round(s) and {} queries", client.rounds(), client.queries());
                     break;
                 }
             },
-            "stop" => {break;},
-            _ => println!("You should enter 'play' or 'stop', you entered {}", input),
+            ["agree"] | ["agree", _] => {
+                let key = words.get(1).copied().unwrap_or("");
+                match values.get(key) {
+                    Some(value) => {
+                        let value = source.and_then(|source| source.current(key)).map(|(_, current)| current).unwrap_or_else(|| value.clone());
+                        print_agreement(&client.agree(&network.ports(), key), &value, client.liar_ratio)
+                    },
+                    None => println!("You should enter 'agree <key>' with one of the keys {}, you entered {}", keys_list(&values), input),
+                }
+            },
+            ["health"] => {
+                monitor.print_report();
+                for (port, restarts) in network.restarts() {
+                    println!("Agent {} : restarted {} time(s)", port, restarts);
+                }
+            },
+            ["reputation"] => print_reputation(client),
+            ["net", ..] => {
+                match proxy {
+                    Some(proxy) => net(proxy, &words[1..]),
+                    None => println!("The client is not routed through the proxy, start the game with --proxy"),
+                }
+            },
+            ["leaderboard"] | ["leaderboard", _] => leaderboard(words.get(1).copied()),
+            ["config", "show"] => print!("{}", show(&config.settings)),
+            ["stop"] => {break;},
+            _ => println!("You should enter 'play', 'agree', 'health', 'reputation', 'net', 'leaderboard', 'config show' or 'stop', you entered {}", input),
         }
-        input.clear();
-        number_of_rounds += 1;
     }
-    stop();
 }
 
-fn stop(){ 
-    /* 
-        Reads config file, connects to every agent and sends them "stop"
-        Necessary to stop the threads.
+fn budget_left<V : NetworkValue>(client : &Client<V>) -> Option<String> {
+    /*
+        Returns the rounds and the queries left, None if the game has no limit.
     */
+    let mut left = Vec::new();
+    if let Some(rounds) = client.rounds_left() {
+        left.push(format!("{} round(s)", rounds));
+    }
+    if let Some(queries) = client.queries_left() {
+        left.push(format!("{} queries", queries));
+    }
+    (!left.is_empty()).then(|| left.join(", "))
+}
 
-    let ports = read_config_file();
-    
-    for port in ports {
-        let addrs = SocketAddr::from(([127, 0, 0, 1], port));
-        match TcpStream::connect(addrs) {
-            Ok(mut stream) => {
-                let b = "stop".as_bytes();
-                stream.write(b).unwrap();
-            }
-            Err(e) => {
-                println!("Failed to connect to {} : {}", port, e);
-            }
-        }
+fn print_reputation<V : NetworkValue>(client : &Client<V>){
+    /*
+        Lists the agents from the least to the most trusted, with their identity once queried and the
+        number of rejected values they answered in this game and per previous game.
+    */
+    let mut agents = client.agents();
+    agents.sort_by(|a, b| client.reputation(*a).total_cmp(&client.reputation(*b)).then(a.cmp(b)));
+    for agent in agents {
+        let identity = client.identity(agent).map(|identity| format!(" ({})", identity)).unwrap_or_default();
+        println!("Agent {}{} : reputation {:.2}, {} rejected value(s) answered, {:.2} per previous game", agent, identity, client.reputation(agent), client.penalties(agent), client.past_penalties(agent));
+    }
+}
+
+fn leaderboard(difficulty : Option<&str>){
+    /*
+        Handles the "leaderboard [easy|medium|hard]" command, every difficulty if none is given.
+    */
+    match difficulty.map(|d| d.parse::<Difficulty>()).transpose() {
+        Ok(difficulty) => print_leaderboard(&scores_file(), difficulty),
+        Err(e) => println!("{}", e),
     }
 }
 
-fn play(already_tried : &mut HashSet<u16>, liar_ratio : f32, value : u16) -> bool { 
+fn net(proxy : &Proxy, args : &[&str]){
     /*
-        Plays a round of the game. It sends "talk" to every agent. The agents will answer with their value.
-        The values are counted in the map "counts". The client compares the frequency of each value to
-        the ratio of agents telling the truth (1 - liar_ratio) and selects the closest one.
+        Handles the "net" command :
+            net                                 shows the links with faults and the groups
+            net group <name> <agents>           defines a group of agents, e.g. net group east 0-4,7
+            net <target> <setting> ...          changes the links of target : all, an agent index or a group
+        The settings are latency=<ms>, jitter=<ms>, drop=<p>, truncate=<p>, partition, heal and reset.
 
-        It sends messages to every agent then receives their answer to avoid waiting for answers from slow agents.
+        Args :
+            - proxy : proxy between the client and the agents
+            - args : words following "net"
+    */
+    let result = match args {
+        [] => {
+            proxy.print_links();
+            Ok(())
+        },
+        ["group", name, agents] => proxy.group(name, agents),
+        [target, settings @ ..] if !settings.is_empty() => proxy.configure(target, settings),
+        _ => Err("You should enter 'net', 'net group <name> <agents>' or 'net <target> <setting> ...'".to_string()),
+    };
+    if let Err(e) = result {
+        println!("{}", e);
+    }
+}
 
-        Args : 
-            - already_tried : set of values played in previous round
+fn print_agreement<V : NetworkValue>(decisions : &[Response<V>], value : &V, liar_ratio : f32){
+    /*
+        Prints the values decided by the agents during an agreement and whether every honest agent
+        decided the network value.
+
+        Args :
+            - decisions : value decided by every agent
+            - value : network value
             - liar_ratio : liar ratio
-            - value : target value 
-        Returns :
-            - bool : true if game is won else false
     */
+    let n = decisions.len();
+    let mut counts : BTreeMap<&V, usize> = BTreeMap::new();
+    for decision in decisions {
+        *counts.entry(&decision.value).or_insert(0) += 1;
+    }
+    let mut counts : Vec<(&V, usize)> = counts.into_iter().collect();
+    counts.sort_by(|(_, c1), (_, c2)| c2.cmp(c1));
+    for (decided, count) in &counts {
+        println!("{} agent(s) decided {}", count, decided);
+    }
 
-    let ports = read_config_file();
-    let size = ports.len() as f32;
+    let honest = n - (liar_ratio * n as f32) as usize;
+    let agreed = decisions.iter().filter(|d| d.value == *value).count();
+    println!("{} agents tolerate {} liar(s)", n, max_faulty(n));
+    if agreed >= honest {
+        println!("The honest agents agreed on the network value");
+    } else {
+        println!("The agreement failed : {} of the {} honest agents decided the network value", agreed, honest);
+    }
+}
 
-    let mut counts : HashMap <u16, f32>= HashMap::new();
-    {
-        let mut tcp_connections = Vec::new();
+fn replay<V : NetworkValue>(path : &str){
+    /*
+        Replays a transcript written with "start ... --record <file>" without spawning any agent.
+        Every recorded round is given to Client::record() and the proposed value is compared to the
+        recorded guess. Any difference is reported as a divergence. The client is given the recorded
+        track record and identities of the agents, and told when a value is found, like in the game.
 
-        // Sending messages
-        for port in ports {
-            let addrs = SocketAddr::from(([127, 0, 0, 1], port));
-            match TcpStream::connect(addrs) {
-                Ok(mut stream) => {
-                    let b = "talk".as_bytes();
-                    stream.write(b).unwrap();
-                    tcp_connections.push(stream);
-                }
-                Err(e) => {
-                    println!("Failed to connect to {} : {}", port, e);
-                }
+        Args :
+            - path : transcript file
+    */
+    let entries = match read_transcript::<V>(path) {
+        Ok(entries) => entries,
+        Err(e) => {
+            println!("Unable to read transcript {} : {}", path, e);
+            return;
+        }
+    };
+
+    let (values, mut client) = match entries.first() {
+        Some(Entry::Config { value_type, value, keys, max_value, num_agents, liar_ratio, seed, track_record }) => {
+            println!("Replaying {} : value_type {:?}, value {:?}, keys {:?}, max_value {}, num_agents {}, liar_ratio {}, seed {}", path, value_type, value, keys, max_value, num_agents, liar_ratio, seed);
+            let mut values = keys.clone();
+            if let Some(value) = value {
+                values.insert(String::new(), value.clone());
             }
+            // Weighs the answers with the reputations the live client had
+            let mut client = Client::new(*liar_ratio, Vec::new);
+            client.set_track_record(track_record.clone());
+            (values, client)
+        },
+        _ => {
+            println!("Transcript {} does not start with a config entry", path);
+            return;
         }
-        // Receiving answers
-        for mut stream in tcp_connections {
-            let mut buffer = [0 as u8; 1024];
-            match stream.read(& mut buffer){
-                Ok(size) => {
-                    let msg = from_utf8(&buffer[..size]).expect("");
-                    if size == 2 {
-                        // [u8,u8]
-                        
-                        // [16-8, 0-8]  
-                        let val : u16 = (buffer[1] as u16) | (buffer[0] as u16) << 8;
-                        println!("Client: received {} from {}", val, stream.peer_addr().unwrap().port());
-                        let count = counts.entry(val).or_insert(0.0);
-                        *count += 1.;
-                    } else {
-                        println!("Client: received incorrect data {} from {}", msg, stream.peer_addr().unwrap().port());
-                    }
-                },
-
-                Err(e) => {
-                    println!("Client failed to read {}", e);
+    };
+
+    let mut divergences = 0;
+    for entry in &entries[1..] {
+        if let Entry::Round { round, key, agents, responses, guess, identities } = entry {
+            client.set_identities(identities.iter().cloned().collect());
+            let replayed = client.record(key, *agents, responses.clone()).guess.clone();
+            let show = |v : &Option<V>| v.as_ref().map(|v| v.to_string()).unwrap_or_else(|| "nothing".to_string());
+            if replayed == *guess {
+                println!("Round {} : proposed {}", round, show(&replayed));
+            } else {
+                divergences += 1;
+                println!("Round {} : DIVERGENCE, recorded {} but replayed {}", round, show(guess), show(&replayed));
+            }
+            if replayed.is_some() && replayed.as_ref() == values.get(key) {
+                client.set_found(key);
+                if key.is_empty() {
+                    println!("The replayed client finds the correct value after {} round(s)", round);
+                } else {
+                    println!("The replayed client finds the value of {} after {} round(s)", key, round);
                 }
             }
         }
     }
-    // (value => frequency)
-    let mut new_key : u16 = 0;
-    let mut min_diff = 2.;
 
-    // (1 => 7,
-    //  2 => 3)
-    for (key, value) in counts {
-        if !already_tried.contains(&key){
-            // |0.3 - 0.7| / |0.3 - 0.3|
-            let diff = f32::abs((1.0 - liar_ratio) - value / size);
-            if diff < min_diff{
-                new_key = key;
-                min_diff = diff;
+    if divergences == 0 {
+        println!("Replay matches the transcript");
+    } else {
+        println!("Replay diverged from the transcript in {} round(s)", divergences);
+    }
+}
+
+fn teardown<V : NetworkValue>(network : &Network<V>){
+    /*
+        Stops every agent, joins their threads and deletes agent.config.
+        Only the first call does something, so that a signal received while the game is being
+        stopped does not stop it twice.
+
+        Args :
+            - network : network of agents
+    */
+    if TEARDOWN_STARTED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    // Sends stop to every agent and join every thread
+    network.shutdown();
+
+    // Deleted agent.config;
+    delete_config_file();
+}
+
+fn install_signal_handler<V : NetworkValue>(network : Arc<Network<V>>){
+    /*
+        On SIGINT or SIGTERM, stops the game the same way as the "stop" command then exits.
+        A second signal exits immediately : the agents are stopped on another thread, so that
+        the handler's thread is free to receive it.
+
+        Args :
+            - network : network of agents
+    */
+    let result = ctrlc::set_handler(move || {
+        if INTERRUPTED.swap(true, Ordering::SeqCst) || TEARDOWN_STARTED.load(Ordering::SeqCst) {
+            println!("Interrupted again, exiting now");
+            process::exit(130);
+        }
+        println!("Interrupted, stopping the agents. Interrupt again to exit now");
+        let network = Arc::clone(&network);
+        thread::spawn(move || {
+            teardown(&network);
+            process::exit(130);
+        });
+    });
+    if let Err(e) = result {
+        error!("Unable to install the signal handler : {}", e);
+    }
+}
+
+fn run_game<V : NetworkValue>(config : Config, seed : u64){
+    /*
+        Plays a game whose network value has type V : launches the agents, runs the game loop until
+        the value is found or "stop" is typed, then stops every agent.
+
+        Args :
+            - config : Config structure, its values are checked already
+            - seed : seed of the game
+    */
+    let mut values : BTreeMap<String, V> = BTreeMap::new();
+    let mut max_value = None;
+    for (key, value) in config.values() {
+        let (value, max) = parse_and_check::<V>(&value, &config.max_value).expect("Config values are checked when parsed");
+        values.insert(key, value);
+        max_value = Some(max);
+    }
+    let max_value = max_value.expect("The network holds at least one value");
+
+    // The agents of a local game keep their identity and their reputation from one game to the next
+    let mut records = match config.multiplayer {
+        Some(_) => BTreeMap::new(),
+        None => reputation::load(&reputation_file()).unwrap_or_else(|e| {
+            error!("Unable to read the reputation of the agents in {} : {}", reputation_file(), e);
+            BTreeMap::new()
+        }),
+    };
+    let track_record = penalties(&records);
+
+    // Open the transcript and record the game settings
+    let recorder = config.record.as_ref().and_then(|path| {
+        let mut keys = values.clone();
+        let entry = Entry::Config {
+            value_type : config.value_type,
+            value : keys.remove(""),
+            keys,
+            max_value : max_value.clone(),
+            num_agents : config.num_agents,
+            liar_ratio : config.liar_ratio,
+            seed,
+            track_record : track_record.clone(),
+        };
+        match Recorder::create(path).and_then(|mut recorder| recorder.record(&entry).map(|_| recorder)) {
+            Ok(recorder) => Some(recorder),
+            Err(e) => {
+                error!("Unable to record the game in {} : {}", path, e);
+                None
             }
         }
+    });
+
+    let options = Options { topology : config.topology.clone(), turncoats : config.turncoats, identities : roster(&records, config.num_agents as usize) };
+
+    // Launches the threads and write config file
+    let network = match Network::spawn_with_registry(values.clone(), max_value.clone(), config.num_agents as usize, config.liar_ratio, seed, &options, write_config_file) {
+        Ok(network) => Arc::new(network),
+        Err(e) => {
+            println!("{}", e);
+            return;
+        }
+    };
+    let graph = network.topology();
+    let (min_degree, max_degree) = graph.degrees();
+    println!("Topology {} : {} edge(s), every agent has {} to {} neighbor(s)", config.topology, graph.edges(), min_degree, max_degree);
+
+    // Ctrl-C and SIGTERM stop the game properly
+    install_signal_handler(Arc::clone(&network));
+
+    // Ping the agents in the background
+    let monitor = {
+        let network = Arc::clone(&network);
+        Monitor::start(move || network.ports(), PING_INTERVAL)
+    };
+
+    // Route the client through the proxy if asked
+    let proxy = match config.proxy.then(|| {
+        let network = Arc::clone(&network);
+        Proxy::start(move || network.ports(), seed)
+    }).transpose() {
+        Ok(proxy) => proxy.map(Arc::new),
+        Err(e) => {
+            println!("Unable to start the proxy : {}", e);
+            monitor.stop();
+            teardown(&network);
+            return;
+        }
+    };
+
+    // Game loop. Waits for "play", "agree", "health", "net" or "stop"
+    let mut client = match &proxy {
+        Some(proxy) => {
+            let proxy = Arc::clone(proxy);
+            Client::new(config.liar_ratio, move || proxy.ports().unwrap_or_else(|e| {
+                error!("Unable to open a front port of the proxy : {}", e);
+                Vec::new()
+            }))
+        },
+        None => {
+            let network = Arc::clone(&network);
+            Client::new(config.liar_ratio, move || network.ports())
+        },
+    };
+    client.set_budget(config.max_rounds, config.query_budget);
+
+    // Push a new value every epoch if asked
+    let source = config.epochs.map(|interval| Source::start(Arc::clone(&network), values.clone(), max_value, interval, seed));
+    client.set_epochs(source.is_some());
+    let chaos = config.chaos.clone().map(|schedule| Chaos::new(schedule, seed));
+    match config.multiplayer {
+        Some(players) => host(client, values[""].clone(), players, config.port, seed),
+        None => {
+            let dashboard = config.tui.then(Dashboard::new);
+
+            client.set_track_record(track_record);
+            let mut game = Game { recorder, monitor : &monitor, network : &network, proxy : proxy.as_deref(), chaos, dashboard, source : source.as_ref(), config : &config };
+            game_loop(values, &mut client, &mut game);
+            merge(&mut records, &client.game_penalties());
+            if let Err(e) = reputation::save(&reputation_file(), &records) {
+                error!("Unable to save the reputation of the agents in {} : {}", reputation_file(), e);
+            }
+        },
+    }
+    if let Some(source) = source {
+        source.stop();
+    }
+    monitor.stop();
+    if let Some(proxy) = proxy {
+        proxy.stop();
     }
 
-    println!("You propose value {}", new_key);
+    // Stop the agents, join every thread and delete agent.config
+    teardown(&network);
+}
 
-    already_tried.insert(new_key);
+fn host<V : NetworkValue>(client : Client<V>, value : V, players : usize, port : u16, seed : u64){
+    /*
+        Hosts a competitive game (see multiplayer.rs) until a player finds the value, every player
+        leaves, or "stop" or the end of stdin, then prints the standings.
 
-    new_key == value
+        Args :
+            - client : queries the agents for the players
+            - value : network value to find
+            - players : number of players
+            - port : port of the game server
+            - seed : seed of the game
+    */
+    let server = match GameServer::start(port, players, client, value, TURN_TIMEOUT, seed) {
+        Ok(server) => Arc::new(server),
+        Err(e) => {
+            println!("Unable to listen on port {} : {}", port, e);
+            return;
+        }
+    };
+    println!("Waiting for {} player(s) on port {}, join with 'join {} <name>' or 'bot {} <name>'. Enter 'stop' to end the game", players, server.port(), server.port(), server.port());
+    {
+        let server = Arc::clone(&server);
+        thread::spawn(move || {
+            let mut input = String::new();
+            while io::stdin().read_line(&mut input).map(|n| n > 0).unwrap_or(false) && input.trim() != "stop" {
+                println!("You should enter 'stop', you entered {}", input);
+                input.clear();
+            }
+            server.stop();
+        });
+    }
+    for (rank, standing) in server.wait().iter().enumerate() {
+        println!("{}. {} : {} agent(s) queried, {} guess(es){}", rank + 1, standing.name, standing.queries, standing.guesses, if standing.won { ", winner" } else { "" });
+    }
 }
 
-fn shutdown(threads : Vec<thread::JoinHandle<()>>) -> () {
-    /* 
-        Joins every thread.
+fn join(port : u16, name : &str){
+    /*
+        Joins a competitive game as a human : prints what the game server sends and sends it the
+        lines typed, e.g. "query" or "guess 3".
 
-        Args : 
-            - threads : threads to be joined
+        Args :
+            - port : port of the game server
+            - name : name of the player
+    */
+    let mut stream = match TcpStream::connect(SocketAddr::from(([127, 0, 0, 1], port))) {
+        Ok(stream) => stream,
+        Err(e) => {
+            println!("Unable to join the game on port {} : {}", port, e);
+            return;
+        }
+    };
+    let reader = stream.try_clone().expect("Unable to clone the stream");
+    thread::spawn(move || {
+        for line in BufReader::new(reader).lines().map_while(Result::ok) {
+            println!("{}", line);
+        }
+        // The game server closes the connection at the end of the game
+        process::exit(0);
+    });
+    let mut input = format!("join {}\n", name);
+    loop {
+        if stream.write_all(input.as_bytes()).is_err() {
+            break;
+        }
+        input.clear();
+        if io::stdin().read_line(&mut input).expect("Error reading input.") == 0 {
+            break;
+        }
+    }
+}
+
+fn serve(port : u16){
+    /*
+        Answers the HTTP API on localhost until "stop", the end of stdin or a signal, then stops
+        the agents of every game.
+
+        Args :
+            - port : port to listen on
     */
-    for thread in threads {
-        thread.join().expect("The thread being joined has panicked");
-        println!("Joined thread");
+    let server = match Server::start(port) {
+        Ok(server) => Arc::new(server),
+        Err(e) => {
+            println!("Unable to listen on port {} : {}", port, e);
+            return;
+        }
+    };
+    {
+        let server = Arc::clone(&server);
+        // Like install_signal_handler(), the games are stopped on another thread and a second signal exits immediately
+        if let Err(e) = ctrlc::set_handler(move || {
+            if INTERRUPTED.swap(true, Ordering::SeqCst) {
+                println!("Interrupted again, exiting now");
+                process::exit(130);
+            }
+            println!("Interrupted, stopping every game. Interrupt again to exit now");
+            let server = Arc::clone(&server);
+            thread::spawn(move || {
+                server.stop();
+                process::exit(130);
+            });
+        }) {
+            error!("Unable to install the signal handler : {}", e);
+        }
     }
-    println!("Joined all threads");
+    println!("Serving the API on http://127.0.0.1:{}, enter 'stop' to stop", server.port());
+    loop {
+        let mut input = String::new();
+        io::stdout().flush().expect("Couldn't flush stdout");
+        if io::stdin().read_line(&mut input).expect("Error reading input.") == 0 || input.trim() == "stop" {
+            break;
+        }
+        println!("You should enter 'stop', you entered {}", input);
+    }
+    server.stop();
 }
 
 fn main() {
 
+    // Logs go to stderr (or a file for the agents), prompts stay on stdout
+    logger::init();
+
     println!("Welcome to liarslie. To start a new game, please type");
     println!("start --value <v> --max-value <max> --num-agents <number> --liar-ratio <ratio>");
-    
-    // Wait for a valid start command and parses it in a Config Structure
-    let config : Config = Config::new();
-
-    // extract useful fields
-    let ratio = config.liar_ratio;
-    let value = config.value;
-
-    // Launches the threads and get the port numbers
-    let (threads, ports) = init(config);
-
-    // Write config file
-    write_config_file(ports);
+    println!("or replay a recorded game with");
+    println!("replay <file>");
+    println!("or drive games through the HTTP API with");
+    println!("serve [port]");
+    println!("or join a competitive game as a human or a bot with");
+    println!("join <port> <name>  /  bot <port> <name>");
+    println!("or show the best scores with");
+    println!("leaderboard [easy|medium|hard]");
+    println!("or show the settings read in {} and the LIARSLIE_* variables with", settings_file());
+    println!("config show [flags]");
+
+    // Reads commands until one of them runs, an invalid start command is typed again
+    let config = loop {
+        let mut input = String::new();
+        io::stdout().flush().expect("Couldn't flush stdout");
+        if io::stdin().read_line(&mut input).expect("Error reading input.") == 0 {
+            return;
+        }
 
-    // Game loop. Waits for "play" or "stop"
-    game_loop(value, ratio);
+        if let Some(path) = input.trim().strip_prefix("replay ") {
+            let path = path.trim();
+            match read_value_type(path) {
+                Ok(ValueType::U16) => replay::<u16>(path),
+                Ok(ValueType::U32) => replay::<u32>(path),
+                Ok(ValueType::U64) => replay::<u64>(path),
+                Ok(ValueType::I64) => replay::<i64>(path),
+                Ok(ValueType::String) => replay::<String>(path),
+                Err(e) => println!("Unable to read transcript {} : {}", path, e),
+            }
+            return;
+        }
 
-    // Join every thread
-    shutdown(threads);
+        let words : Vec<&str> = input.split_whitespace().collect();
+        match words[..] {
+            ["serve"] => return serve(DEFAULT_API_PORT),
+            ["serve", port] => {
+                match port.parse::<u16>() {
+                    Ok(port) => serve(port),
+                    Err(_) => println!("port should be a 16b integer, got {}", port),
+                }
+                return;
+            },
+            ["leaderboard"] | ["leaderboard", _] => return leaderboard(words.get(1).copied()),
+            ["config", "show", ..] => {
+                // Settings a start command with these flags would use, "show" stands for "start"
+                let words = shellwords::split(&input).map_err(|e| ConfigError::Command(e.to_string()));
+                match words.and_then(|words| settings(words[1..].to_vec())) {
+                    Ok(settings) => print!("{}", show(&settings)),
+                    Err(e) => println!("{}", e),
+                }
+                continue;
+            },
+            ["join", port, name] | ["bot", port, name] => {
+                match port.parse::<u16>() {
+                    Ok(port) if words[0] == "join" => join(port, name),
+                    Ok(port) => match bot(port, name) {
+                        Ok(result) => println!("{}", result),
+                        Err(e) => println!("The bot stopped : {}", e),
+                    },
+                    Err(_) => println!("port should be a 16b integer, got {}", port),
+                }
+                return;
+            },
+            _ => {},
+        }
 
-    // Deleted agent.config;
-    delete_config_file();
-    
+        // Parses the start command in a Config Structure, or asks for it again
+        match Config::new(&input) {
+            Ok(config) => break config,
+            Err(e) => {
+                println!("{}", e);
+                println!("Please type the start command again, e.g.");
+                println!("start --value <v> --max-value <max> --num-agents <number> --liar-ratio <ratio>");
+            },
+        }
+    };
+
+    // Use the given seed or draw one and display it so that the game can be reproduced
+    let seed = match config.seed {
+        Some(seed) => seed,
+        None => {
+            let seed = rand::random();
+            println!("Using seed {}", seed);
+            seed
+        }
+    };
+
+    match config.value_type {
+        ValueType::U16 => run_game::<u16>(config, seed),
+        ValueType::U32 => run_game::<u32>(config, seed),
+        ValueType::U64 => run_game::<u64>(config, seed),
+        ValueType::I64 => run_game::<i64>(config, seed),
+        ValueType::String => run_game::<String>(config, seed),
+    }
 }
@@ -0,0 +1,432 @@
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex, mpsc};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use log::{info, warn};
+use crate::client::{Client, Response};
+use crate::value::{NetworkValue, ValueType};
+
+/*
+    Competitive mode : several players connect to the game server and take turns against the same
+    network. The server speaks a line protocol, so a human can play with netcat as well as a bot.
+
+        player -> server                        server -> player
+        join <name>                             waiting <joined>/<players>  to every player
+                                                welcome <name> <value type> <liar ratio> <agents>
+                                                start <name>,<name>,...     to every player
+                                                turn <name>                 to every player
+        query [count]                           answers <asked> [<value>, ...]
+        guess <value>                           wrong <value>
+                                                timeout <name>              to every player
+                                                left <name>                 to every player
+                                                error <message>
+                                                winner <name> <queries> <guesses>
+                                                standing <rank> <name> <queries> <guesses>
+                                                over
+
+    On its turn, a player either queries count random agents (all of them by default) or guesses
+    the network value. The first player to guess it wins, the others are ranked by their number of
+    agents queried. Commands sent out of turn are refused.
+*/
+
+pub const TURN_TIMEOUT : Duration = Duration::from_millis(30000);    // Time after which a silent player loses its turn
+pub const JOIN_TIMEOUT : Duration = Duration::from_millis(10000);    // Time given to a new connection to send join
+const POLL_INTERVAL : Duration = Duration::from_millis(100);         // Time between two checks that the server is still running
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Standing{
+    pub name : String,
+    pub queries : usize,                // Number of agents queried
+    pub guesses : usize,                // Number of guesses
+    pub won : bool,
+}
+
+struct Player{
+    standing : Standing,
+    stream : TcpStream,
+    left : bool,                        // Set once the player is disconnected
+}
+
+enum Message{
+    Line(usize, String),                // (player, line it sent)
+    Left(usize),                        // Player disconnected
+}
+
+fn send(stream : &mut TcpStream, line : &str) {
+    if let Err(e) = writeln!(stream, "{}", line) {
+        warn!("Game server: failed to send {} : {}", line, e);
+    }
+}
+
+fn broadcast(players : &mut [Player], line : &str) {
+    for player in players.iter_mut().filter(|p| !p.left) {
+        send(&mut player.stream, line);
+    }
+}
+
+fn standings(players : &[Player]) -> Vec<Standing> {
+    /*
+        Returns the standings : the winner first, then the players who played, those who queried the
+        fewest agents first, then the players who neither queried nor guessed.
+    */
+    let mut standings : Vec<Standing> = players.iter().map(|p| p.standing.clone()).collect();
+    standings.sort_by_key(|s| (!s.won, s.queries == 0 && s.guesses == 0, s.queries, s.guesses));
+    standings
+}
+
+fn join(listener : &TcpListener, players : &mut Vec<Player>, size : usize, running : &AtomicBool) -> io::Result<()> {
+    /*
+        Accepts connections until size players have joined with different names.
+    */
+    while players.len() < size {
+        let (stream, _) = listener.accept()?;
+        if !running.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+        let mut stream = stream;
+        stream.set_read_timeout(Some(JOIN_TIMEOUT))?;
+        let mut line = String::new();
+        if BufReader::new(stream.try_clone()?).read_line(&mut line).is_err() {
+            continue;
+        }
+        let words : Vec<&str> = line.split_whitespace().collect();
+        match words[..] {
+            ["join", name] if players.iter().any(|p| p.standing.name == name) => send(&mut stream, &format!("error {} is taken", name)),
+            ["join", name] => {
+                stream.set_read_timeout(None)?;
+                players.push(Player {
+                    standing : Standing { name : name.to_string(), queries : 0, guesses : 0, won : false },
+                    stream,
+                    left : false,
+                });
+                info!("Game server: {} joined ({}/{})", name, players.len(), size);
+                let line = format!("waiting {}/{}", players.len(), size);
+                broadcast(players, &line);
+            },
+            _ => send(&mut stream, "error you should send join <name>"),
+        }
+    }
+    Ok(())
+}
+
+struct Match<V : NetworkValue>{
+    client : Client<V>,                 // Queries the agents for the players
+    value : V,                          // Network value to find
+    turn_timeout : Duration,
+    rng : StdRng,                       // Draws the agents queried
+}
+
+impl<V : NetworkValue> Match<V> {
+    fn handle(&mut self, player : &mut Player, line : &str) -> Result<(), String> {
+        /*
+            Handles the command of the player whose turn it is. The turn is over unless the command
+            is invalid.
+        */
+        let words : Vec<&str> = line.split_whitespace().collect();
+        match words[..] {
+            ["query"] | ["query", _] => {
+                let mut ports = self.client.agents();
+                let count = match words.get(1) {
+                    Some(count) => match count.parse::<usize>() {
+                        Ok(count) if (1..=ports.len()).contains(&count) => count,
+                        _ => return Err(format!("count should be in [1 ; {}]", ports.len())),
+                    },
+                    None => ports.len(),
+                };
+                ports.shuffle(&mut self.rng);
+                ports.truncate(count);
+                let values : Vec<V> = self.client.query(&ports, "").into_iter().map(|response| response.value).collect();
+                player.standing.queries += count;
+                send(&mut player.stream, &format!("answers {} {}", count, serde_json::to_string(&values).expect("Values are serializable")));
+                Ok(())
+            },
+            ["guess", guess] => {
+                let guess = guess.parse::<V>().map_err(|_| format!("guess should be a {}", V::NAME))?;
+                player.standing.guesses += 1;
+                if guess == self.value {
+                    player.standing.won = true;
+                } else {
+                    send(&mut player.stream, &format!("wrong {}", guess));
+                }
+                Ok(())
+            },
+            _ => Err("you should send query [count] or guess <value>".to_string()),
+        }
+    }
+
+    fn play(&mut self, players : &mut [Player], messages : &mpsc::Receiver<Message>, running : &AtomicBool) {
+        /*
+            Gives the turn to every player in order until one guesses the network value, every
+            player has left or the server is stopped.
+        */
+        let names : Vec<String> = players.iter().map(|p| p.standing.name.clone()).collect();
+        broadcast(players, &format!("start {}", names.join(",")));
+        let mut current = 0;
+        while running.load(Ordering::SeqCst) && players.iter().any(|p| !p.left) {
+            if players[current].left {
+                current = (current + 1) % players.len();
+                continue;
+            }
+            let name = players[current].standing.name.clone();
+            broadcast(players, &format!("turn {}", name));
+            let deadline = Instant::now() + self.turn_timeout;
+            loop {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining.is_zero() {
+                    info!("Game server: {} ran out of time", name);
+                    broadcast(players, &format!("timeout {}", name));
+                    break;
+                }
+                match messages.recv_timeout(remaining.min(POLL_INTERVAL)) {
+                    Ok(Message::Line(i, _)) if i != current => send(&mut players[i].stream, "error not your turn"),
+                    Ok(Message::Line(i, line)) => match self.handle(&mut players[i], &line) {
+                        Ok(_) if players[i].standing.won => {
+                            let standing = &players[i].standing;
+                            info!("Game server: {} wins after querying {} agent(s)", name, standing.queries);
+                            let line = format!("winner {} {} {}", name, standing.queries, standing.guesses);
+                            broadcast(players, &line);
+                            return;
+                        },
+                        Ok(_) => break,
+                        Err(e) => send(&mut players[i].stream, &format!("error {}", e)),
+                    },
+                    Ok(Message::Left(i)) => {
+                        players[i].left = true;
+                        info!("Game server: {} left", players[i].standing.name);
+                        broadcast(players, &format!("left {}", players[i].standing.name));
+                        if i == current {
+                            break;
+                        }
+                    },
+                    Err(mpsc::RecvTimeoutError::Timeout) if running.load(Ordering::SeqCst) => {},
+                    Err(_) => return,
+                }
+            }
+            current = (current + 1) % players.len();
+        }
+    }
+}
+
+pub struct GameServer{
+    port : u16,                         // Port listened on, on localhost
+    running : Arc<AtomicBool>,          // Cleared to stop the server
+    thread : Mutex<Option<thread::JoinHandle<Vec<Standing>>>>,
+}
+
+impl GameServer {
+    pub fn start<V : NetworkValue>(port : u16, size : usize, client : Client<V>, value : V, turn_timeout : Duration, seed : u64) -> io::Result<Self> {
+        /*
+            Args :
+                - port : port to listen on, on localhost. 0 for any free port
+                - size : number of players, the game starts once they have all joined
+                - client : queries the agents for the players
+                - value : network value to find
+                - turn_timeout : time after which a silent player loses its turn
+                - seed : seed of the draw of the agents queried
+            Returns :
+                - GameServer waiting for the players in the background
+        */
+        let listener = TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], port)))?;
+        let port = listener.local_addr()?.port();
+        let running = Arc::new(AtomicBool::new(true));
+        let (liar_ratio, agents) = (client.liar_ratio, client.agents().len());
+        let welcome = move |name : &str| format!("welcome {} {} {} {}", name, V::NAME, liar_ratio, agents);
+        let thread = {
+            let running = Arc::clone(&running);
+            thread::spawn(move || {
+                let mut players : Vec<Player> = Vec::new();
+                if let Err(e) = join(&listener, &mut players, size, &running) {
+                    warn!("Game server: failed to accept players : {}", e);
+                }
+                drop(listener);
+
+                // A thread per player forwards its lines
+                let (sender, messages) = mpsc::channel();
+                for (i, player) in players.iter_mut().enumerate() {
+                    send(&mut player.stream, &welcome(&player.standing.name));
+                    let stream = player.stream.try_clone().expect("Unable to clone a player's stream");
+                    let sender = sender.clone();
+                    thread::spawn(move || {
+                        for line in BufReader::new(stream).lines() {
+                            match line {
+                                Ok(line) if !line.trim().is_empty() => {
+                                    if sender.send(Message::Line(i, line)).is_err() {
+                                        return;
+                                    }
+                                },
+                                Ok(_) => {},
+                                Err(_) => break,
+                            }
+                        }
+                        let _ = sender.send(Message::Left(i));
+                    });
+                }
+                drop(sender);
+
+                if players.len() == size {
+                    let mut game = Match { client, value, turn_timeout, rng : StdRng::seed_from_u64(seed) };
+                    game.play(&mut players, &messages, &running);
+                }
+                let standings = standings(&players);
+                for (rank, standing) in standings.iter().enumerate() {
+                    broadcast(&mut players, &format!("standing {} {} {} {}", rank + 1, standing.name, standing.queries, standing.guesses));
+                }
+                broadcast(&mut players, "over");
+                for player in &players {
+                    let _ = player.stream.shutdown(Shutdown::Both);
+                }
+                standings
+            })
+        };
+        Ok(Self { port, running, thread : Mutex::new(Some(thread)) })
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    pub fn stop(&self) {
+        /*
+            Ends the game, the players get the standings so far.
+        */
+        if self.running.swap(false, Ordering::SeqCst) {
+            // Wakes the thread up if it waits for players
+            let _ = TcpStream::connect(SocketAddr::from(([127, 0, 0, 1], self.port)));
+        }
+    }
+
+    pub fn wait(&self) -> Vec<Standing> {
+        /*
+            Waits for the end of the game and returns the standings.
+        */
+        match self.thread.lock().expect("Game server lock poisoned").take() {
+            Some(thread) => thread.join().expect("The game server thread has panicked"),
+            None => Vec::new(),
+        }
+    }
+}
+
+fn bot_with<V : NetworkValue>(name : &str, liar_ratio : f32, lines : &mut impl Iterator<Item = io::Result<String>>, stream : &mut TcpStream) -> io::Result<String> {
+    /*
+        Plays with the client's estimator : queries every agent, then guesses the value the client
+        would propose, and again.
+    */
+    let mut client : Client<V> = Client::new(liar_ratio, Vec::new);
+    let mut candidate : Option<V> = None;
+    for line in lines {
+        let line = line?;
+        let words : Vec<&str> = line.splitn(3, ' ').collect();
+        match words[..] {
+            ["turn", player] if player == name => match candidate.take() {
+                Some(value) => writeln!(stream, "guess {}", value)?,
+                None => writeln!(stream, "query")?,
+            },
+            ["answers", asked, values] => {
+                let values : Vec<V> = serde_json::from_str(values).map_err(io::Error::other)?;
+                let asked = asked.parse().unwrap_or(values.len());
+                let responses = values.into_iter().map(|value| Response { agent : 0, value, epoch : 0 }).collect();
+                candidate = client.record("", asked, responses).guess.clone();
+            },
+            ["winner", ..] => return Ok(line),
+            ["over"] => break,
+            _ => {},
+        }
+    }
+    Ok("over".to_string())
+}
+
+pub fn bot(port : u16, name : &str) -> io::Result<String> {
+    /*
+        Joins the game server listening on port and plays until the end of the game.
+
+        Args :
+            - port : port of the game server
+            - name : name of the bot
+        Returns :
+            - String : the line announcing the winner, or "over" if the game ended without one
+    */
+    let mut stream = TcpStream::connect(SocketAddr::from(([127, 0, 0, 1], port)))?;
+    writeln!(stream, "join {}", name)?;
+    let mut lines = BufReader::new(stream.try_clone()?).lines();
+    while let Some(line) = lines.next() {
+        let line = line?;
+        let words : Vec<&str> = line.split_whitespace().collect();
+        match words[..] {
+            ["welcome", _, value_type, liar_ratio, _] => {
+                let liar_ratio = liar_ratio.parse().map_err(io::Error::other)?;
+                return match value_type.parse::<ValueType>().map_err(io::Error::other)? {
+                    ValueType::U16 => bot_with::<u16>(name, liar_ratio, &mut lines, &mut stream),
+                    ValueType::U32 => bot_with::<u32>(name, liar_ratio, &mut lines, &mut stream),
+                    ValueType::U64 => bot_with::<u64>(name, liar_ratio, &mut lines, &mut stream),
+                    ValueType::I64 => bot_with::<i64>(name, liar_ratio, &mut lines, &mut stream),
+                    ValueType::String => bot_with::<String>(name, liar_ratio, &mut lines, &mut stream),
+                };
+            },
+            ["error", ..] => return Err(io::Error::other(line)),
+            _ => {},
+        }
+    }
+    Ok("over".to_string())
+}
+
+
+/*---------------------------- TESTS ----------------------------*/
+
+#[cfg(test)]
+mod tests {
+    use crate::client::Client;
+    use crate::multiplayer::{GameServer, bot};
+    use crate::network::Network;
+    use std::collections::BTreeMap;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpStream;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_multiplayer() {
+        let values = BTreeMap::from([(String::new(), 3_u16)]);
+        let network = Arc::new(Network::spawn(values, 10, 10, 0.3, 42).unwrap());
+        let client = {
+            let network = Arc::clone(&network);
+            Client::<u16>::new(0.3, move || network.ports())
+        };
+        let server = GameServer::start(0, 3, client, 3, Duration::from_millis(500), 42).unwrap();
+        let port = server.port();
+
+        // A human who plays out of turn then lets the time run out
+        let mut human = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        writeln!(human, "join carol").unwrap();
+        let mut lines = BufReader::new(human.try_clone().unwrap()).lines().map(|l| l.unwrap());
+        assert_eq!(lines.next().unwrap(), "waiting 1/3");
+
+        // alice joins before bob
+        let alice = thread::spawn(move || bot(port, "alice").unwrap());
+        assert_eq!(lines.next().unwrap(), "waiting 2/3");
+        let bots = vec![alice, thread::spawn(move || bot(port, "bob").unwrap())];
+        assert!(lines.by_ref().any(|line| line == "welcome carol u16 0.3 10"));
+        assert_eq!(lines.next().unwrap(), "start carol,alice,bob");
+        assert_eq!(lines.next().unwrap(), "turn carol");
+        writeln!(human, "guess x").unwrap();
+        assert_eq!(lines.next().unwrap(), "error guess should be a u16");
+        assert_eq!(lines.next().unwrap(), "timeout carol");
+        assert_eq!(lines.next().unwrap(), "turn alice");
+        writeln!(human, "query").unwrap();
+        assert_eq!(lines.next().unwrap(), "error not your turn");
+
+        // alice queries first, so she finds the value first
+        let winners : Vec<String> = bots.into_iter().map(|bot| bot.join().unwrap()).collect();
+        assert_eq!(winners, vec!["winner alice 10 1", "winner alice 10 1"]);
+        let standings = server.wait();
+        assert_eq!(standings.iter().map(|s| s.name.as_str()).collect::<Vec<_>>(), vec!["alice", "bob", "carol"]);
+        assert!(standings[0].won);
+        assert!(lines.any(|line| line == "over"));
+        network.shutdown();
+    }
+}
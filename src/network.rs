@@ -0,0 +1,350 @@
+use std::collections::{BTreeMap, HashSet};
+use std::io::Write;
+use std::net::{Shutdown, SocketAddr, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use rand::{Rng, SeedableRng};
+use rand::seq::index::sample;
+use rand::rngs::StdRng;
+use log::{debug, info, warn};
+use crate::agent::new_identity;
+use crate::client::send_stop;
+use crate::supervisor::{AgentSpec, Supervisor};
+use crate::topology::{Graph, Topology};
+use crate::turncoat::Turncoats;
+use crate::value::NetworkValue;
+
+#[derive(Debug, Clone, Default)]
+pub struct Options{
+    pub topology : Topology,            // Who talks to whom among the agents, every agent to every other by default
+    pub turncoats : Turncoats,          // Agents changing sides during the game, none by default
+    pub identities : Vec<String>,       // Identity of the first agents, kept from previous games, the others get a new one
+}
+
+pub struct Network<V : NetworkValue>{
+    supervisor : Supervisor<V>,
+    values : BTreeMap<String, V>,       // Network value of every key, the empty key for a single value
+    liar_ratio : f32,
+    rng : Mutex<StdRng>,                // Draws the liars and the seeds of the agents added by extend()
+    graph : Arc<Mutex<Graph>>,          // Neighbors of every agent, in the supervisor's order
+}
+
+fn send_neighbors(graph : &Graph, ports : &[u16]) {
+    /*
+        Sends "neighbors <ports>" to every agent with the ports of its neighbors in graph.
+    */
+    for (agent, &port) in ports.iter().enumerate().take(graph.len()) {
+        let neighbors : Vec<u16> = graph.neighbors(agent).iter().filter_map(|&b| ports.get(b).cloned()).collect();
+        let msg = format!("neighbors {}", serde_json::to_string(&neighbors).expect("Ports are serializable"));
+        let sent = TcpStream::connect(SocketAddr::from(([127, 0, 0, 1], port))).and_then(|mut stream| {
+            stream.write_all(msg.as_bytes())?;
+            stream.shutdown(Shutdown::Write)
+        });
+        if let Err(e) = sent {
+            debug!("Failed to send neighbors to {} : {}", port, e);
+        }
+    }
+}
+
+fn side(identity : &str, key : &str) -> u64 {
+    /*
+        Returns the draw of the agent with identity for key, the same in every game : the liars being
+        the agents with the lowest draws, an agent kept from a previous game keeps its side (as long
+        as the other agents are the same) and its reputation is earned, not drawn again every game.
+        FNV-1a then a generator, which unlike DefaultHasher gives the same draw with every Rust version.
+    */
+    let hash = identity.bytes().chain([0]).chain(key.bytes())
+        .fold(0xcbf29ce484222325_u64, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3));
+    StdRng::seed_from_u64(hash).gen()
+}
+
+impl<V : NetworkValue> Network<V>{
+    pub fn spawn(values : BTreeMap<String, V>, max_value : V, num_agents : usize, liar_ratio : f32, seed : u64) -> Result<Self, String> {
+        /*
+            Launches a network of num_agents agents talking to each other, see spawn_with_registry().
+        */
+        Self::spawn_with_registry(values, max_value, num_agents, liar_ratio, seed, &Options::default(), |_| {})
+    }
+
+    pub fn spawn_with_registry<F>(values : BTreeMap<String, V>, max_value : V, num_agents : usize, liar_ratio : f32, seed : u64, options : &Options, registry : F) -> Result<Self, String>
+    where F : Fn(Vec<u16>) + Send + Sync + 'static {
+        /*
+            Launches a total of num_agents agents' threads. For every key, liar_ratio * num_agents agents
+            chosen at random lie and the rest are telling the truth.
+            The liars and the seed of every agent are drawn from a generator seeded with seed, so the same
+            seed always produces the same liars' values. The agents given an identity by options draw
+            their side from it instead, see side().
+
+            For every key, options.turncoats.traitors of the honest agents turn liar and options.turncoats.reformed of
+            the liars turn honest when their trigger is reached, see turncoat.rs.
+
+            Agent i is given options.identities[i] if any, otherwise a new identity (see Agent::identity).
+            The agents are launched by a supervisor which restarts them if they crash. Every agent
+            is sent the ports of its neighbors in options.topology each time the ports change.
+
+            Args :
+                - values : network value of every key, the empty key for a single value
+                - max_value : maximum value
+                - num_agents : number of agents
+                - liar_ratio : ratio of liars for every key
+                - seed : seed of the network
+                - options : topology, turncoats and identities of the agents, see Options
+                - registry : called with the ports of all the agents every time they change
+            Returns :
+                - Network, or a message explaining why it cannot be built : no agent, a ratio out of
+                  [0 ; 1[ (turncoats' ratios out of [0 ; 1]) or a topology which cannot be built
+        */
+        let Options { topology, turncoats, identities } = options;
+        if num_agents == 0 {
+            return Err("A network needs at least one agent".to_string());
+        }
+        if !(0. ..1.).contains(&liar_ratio) {
+            return Err(format!("The liar ratio should be in [0 ; 1[, got {}", liar_ratio));
+        }
+        for (name, ratio) in [("traitors", turncoats.traitors), ("reformed", turncoats.reformed)] {
+            if !(0. ..=1.).contains(&ratio) {
+                return Err(format!("The ratio of {} should be in [0 ; 1], got {}", name, ratio));
+            }
+        }
+        let number_of_liars = (liar_ratio * (num_agents as f32)) as usize;
+        let mut rng = StdRng::seed_from_u64(seed);
+
+        // Choose the liars of every key independently : the agents with the lowest draws lie
+        let liars : Vec<HashSet<usize>> = values.keys().map(|key| {
+            let draws : Vec<u64> = (0..num_agents)
+                .map(|i| identities.get(i).map(|identity| side(identity, key)).unwrap_or_else(|| rng.gen()))
+                .collect();
+            let mut agents : Vec<usize> = (0..num_agents).collect();
+            agents.sort_by_key(|&i| draws[i]);
+            agents.into_iter().take(number_of_liars).collect()
+        }).collect();
+
+        // Choose the traitors among the honest agents and the reformed liars among the liars of every key
+        let turning_agents : Vec<HashSet<usize>> = liars.iter().map(|liars| {
+            let (liars, honest) : (Vec<usize>, Vec<usize>) = (0..num_agents).partition(|i| liars.contains(i));
+            let mut chosen = HashSet::new();
+            for (agents, ratio) in [(honest, turncoats.traitors), (liars, turncoats.reformed)] {
+                let count = (ratio * agents.len() as f32) as usize;
+                chosen.extend(sample(&mut rng, agents.len(), count).into_iter().map(|i| agents[i]));
+            }
+            chosen
+        }).collect();
+
+        let specs : Vec<AgentSpec<V>> = (0..num_agents).map(|i| {
+            let keys = values.iter().zip(&liars)
+                .map(|((key, value), liars)| (key.clone(), value.clone(), liars.contains(&i)))
+                .collect();
+            let turning : HashSet<String> = values.keys().zip(&turning_agents)
+                .filter(|(_, agents)| agents.contains(&i))
+                .map(|(key, _)| key.clone())
+                .collect();
+            let turn = (!turning.is_empty()).then(|| (turncoats.trigger.draw(&mut rng), turning));
+            let identity = identities.get(i).cloned().unwrap_or_else(new_identity);
+            AgentSpec { keys, seed : rng.gen(), turn, identity }
+        }).collect();
+
+        let graph = Arc::new(Mutex::new(topology.build(num_agents, &mut rng)?));
+        let registry = {
+            let graph = Arc::clone(&graph);
+            move |ports : Vec<u16>| {
+                send_neighbors(&graph.lock().expect("Network lock poisoned"), &ports);
+                registry(ports);
+            }
+        };
+
+        Ok(Self {
+            supervisor : Supervisor::start(specs, max_value, registry),
+            values,
+            liar_ratio,
+            rng : Mutex::new(rng),
+            graph,
+        })
+    }
+
+    pub fn ports(&self) -> Vec<u16> {
+        /*
+            Returns the port of every agent of the network.
+        */
+        self.supervisor.ports()
+    }
+
+    pub fn restarts(&self) -> Vec<(u16, u32)> {
+        /*
+            Returns (port, number of restarts) of every agent restarted at least once.
+        */
+        self.supervisor.restarts()
+    }
+
+    pub fn topology(&self) -> Graph {
+        /*
+            Returns the neighbors of every agent, agent i listens on ports()[i].
+        */
+        self.graph.lock().expect("Network lock poisoned").clone()
+    }
+
+    pub fn extend(&self, num_agents : usize) -> Vec<u16> {
+        /*
+            Adds num_agents agents to the network. Each of them lies about each key with probability liar_ratio.
+            They are connected to the other agents as described in Graph::grow().
+
+            Args :
+                - num_agents : number of agents to add
+            Returns :
+                - Vec<u16> : ports of the new agents
+        */
+        let mut rng = self.rng.lock().expect("Network lock poisoned");
+        let specs : Vec<AgentSpec<V>> = (0..num_agents).map(|_| {
+            let keys = self.values.iter()
+                .map(|(key, value)| (key.clone(), value.clone(), rng.gen_bool(self.liar_ratio as f64)))
+                .collect();
+            AgentSpec { keys, seed : rng.gen(), turn : None, identity : new_identity() }
+        }).collect();
+        self.graph.lock().expect("Network lock poisoned").grow(num_agents, &mut *rng);
+        self.supervisor.add(specs)
+    }
+
+    pub fn kill(&self, port : u16) -> bool {
+        /*
+            Stops the agent listening on port and removes it from the network.
+
+            Args :
+                - port : port of the agent
+            Returns :
+                - bool : false if no agent of the network listens on port
+        */
+        if let Some(agent) = self.ports().iter().position(|&p| p == port) {
+            self.graph.lock().expect("Network lock poisoned").remove(agent);
+        }
+        // A stopped agent has no thread left to join
+        let stopped = self.stopped().contains(&port);
+        match self.supervisor.remove(port) {
+            Some(thread) => {
+                send_stop(port);
+                if thread.join().is_err() {
+                    warn!("The thread of agent {} has panicked", port);
+                }
+                true
+            },
+            None => stopped,
+        }
+    }
+
+    pub fn stop_agent(&self, port : u16) -> bool {
+        /*
+            Stops the agent listening on port but keeps it in the network, its port does not answer
+            anymore until it is revived.
+
+            Args :
+                - port : port of the agent
+            Returns :
+                - bool : false if no running agent of the network listens on port
+        */
+        match self.supervisor.pause(port) {
+            Some(thread) => {
+                send_stop(port);
+                if thread.join().is_err() {
+                    warn!("The thread of agent {} has panicked", port);
+                }
+                true
+            },
+            None => false,
+        }
+    }
+
+    pub fn revive(&self, port : u16) -> Option<u16> {
+        /*
+            Launches again an agent stopped by stop_agent(), with the same values, on a new port.
+
+            Returns :
+                - Option<u16> : new port of the agent, None if no stopped agent listened on port
+        */
+        self.supervisor.revive(port)
+    }
+
+    pub fn restart(&self, port : u16) -> Option<u16> {
+        /*
+            Stops the agent listening on port and launches it again on a new port.
+
+            Returns :
+                - Option<u16> : new port of the agent, None if no running agent listens on port
+        */
+        if self.stop_agent(port) { self.revive(port) } else { None }
+    }
+
+    pub fn stopped(&self) -> Vec<u16> {
+        /*
+            Returns the port of every agent stopped by stop_agent().
+        */
+        self.supervisor.paused()
+    }
+
+    pub fn freeze(&self, port : u16, duration : Duration) {
+        /*
+            Makes the agent listening on port stop handling messages during duration.
+        */
+        let msg = format!("freeze {}", duration.as_millis());
+        let sent = TcpStream::connect(SocketAddr::from(([127, 0, 0, 1], port))).and_then(|mut stream| stream.write_all(msg.as_bytes()));
+        if let Err(e) = sent {
+            warn!("Failed to freeze {} : {}", port, e);
+        }
+    }
+
+    pub fn shutdown(&self) {
+        /*
+            Stops every agent and joins their threads. Stopped agents are not restarted.
+        */
+        self.supervisor.stop_watching();
+        let stopped = self.supervisor.paused();
+        for port in self.supervisor.ports().into_iter().filter(|port| !stopped.contains(port)) {
+            send_stop(port);
+        }
+        self.supervisor.shutdown();
+        info!("Network stopped");
+    }
+}
+
+
+/*---------------------------- TESTS ----------------------------*/
+
+#[cfg(test)]
+mod tests {
+    use crate::client::Client;
+    use crate::network::Network;
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_network() {
+        let values = BTreeMap::from([(String::new(), 3_u16)]);
+        let network = Arc::new(Network::spawn(values, 10, 10, 0.3, 42).unwrap());
+        let mut client = {
+            let network = Arc::clone(&network);
+            Client::<u16>::new(0.3, move || network.ports())
+        };
+        assert_eq!(client.agents().len(), 10);
+
+        let round = client.play("").unwrap();
+        assert_eq!(round.responses.len(), 10);
+        assert_eq!(round.responses.iter().filter(|r| r.value == 3).count(), 7);
+        assert_eq!(round.guess, Some(3));
+
+        let added = network.extend(2);
+        assert_eq!(added.len(), 2);
+        assert_eq!(client.agents().len(), 12);
+
+        assert!(network.kill(added[0]));
+        assert!(!network.kill(added[0]));
+        assert_eq!(client.agents().len(), 11);
+        assert_eq!(client.play("").unwrap().responses.len(), 11);
+
+        network.shutdown();
+        assert!(client.play("").unwrap().responses.is_empty());
+        assert_eq!(client.rounds(), 3);
+
+        // Invalid settings are errors rather than panics
+        let values = BTreeMap::from([(String::new(), 3_u16)]);
+        assert!(Network::spawn(values.clone(), 10, 0, 0.3, 42).is_err());
+        assert!(Network::spawn(values.clone(), 10, 10, 1.5, 42).is_err());
+        assert!(Network::spawn(values, 10, 10, -0.1, 42).is_err());
+    }
+}
@@ -0,0 +1,419 @@
+use std::collections::{BTreeMap, HashMap};
+use std::collections::hash_map::Entry;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use log::{debug, warn};
+
+/*
+    In-process emulation of a bad network between the client and the agents. The proxy listens on
+    a front port per agent and forwards every connection to the agent, after applying the faults
+    of the agent's link. Agents are designated by their index, agent i listens on ports()[i].
+    Links are kept by the port of their agent, so that a link follows its agent when the agents
+    before it leave the network. The agents talk to each other directly.
+*/
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Link{
+    pub latency : Duration,             // Delay added in each direction
+    pub jitter : Duration,              // Random variation of the latency, in [-jitter ; jitter]
+    pub drop : f64,                     // Probability that a request never reaches the agent
+    pub truncate : f64,                 // Probability that a reply is cut short
+    pub partitioned : bool,             // The agent cannot be reached at all
+}
+
+impl Link {
+    pub fn apply(&mut self, setting : &str) -> Result<(), String> {
+        /*
+            Changes the link according to a setting : latency=<ms>, jitter=<ms>, drop=<probability>,
+            truncate=<probability>, partition, heal or reset.
+        */
+        let ms = |v : &str| v.trim_end_matches("ms").parse::<u64>().map(Duration::from_millis)
+            .map_err(|_| format!("{} should be a number of milliseconds", setting));
+        let probability = |v : &str| match v.parse::<f64>() {
+            Ok(p) if (0. ..=1.).contains(&p) => Ok(p),
+            _ => Err(format!("{} should be a probability in [0 ; 1]", setting)),
+        };
+        match setting.split_once('=') {
+            Some(("latency", v)) => self.latency = ms(v)?,
+            Some(("jitter", v)) => self.jitter = ms(v)?,
+            Some(("drop", v)) => self.drop = probability(v)?,
+            Some(("truncate", v)) => self.truncate = probability(v)?,
+            None if setting == "partition" => self.partitioned = true,
+            None if setting == "heal" => self.partitioned = false,
+            None if setting == "reset" => *self = Link::default(),
+            _ => return Err(format!("setting should be latency=<ms>, jitter=<ms>, drop=<p>, truncate=<p>, partition, heal or reset, got {}", setting)),
+        }
+        Ok(())
+    }
+
+    fn delay<R : Rng>(&self, rng : &mut R) -> Duration {
+        /*
+            Draws the one way delay of a message.
+        */
+        let jitter = self.jitter.as_micros() as i64;
+        let jitter = if jitter > 0 { rng.gen_range(-jitter..=jitter) } else { 0 };
+        Duration::from_micros((self.latency.as_micros() as i64 + jitter).max(0) as u64)
+    }
+}
+
+impl fmt::Display for Link {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        if self.partitioned {
+            return write!(f, "partitioned");
+        }
+        write!(f, "latency {} ms, jitter {} ms, drop {}, truncate {}", self.latency.as_millis(), self.jitter.as_millis(), self.drop, self.truncate)
+    }
+}
+
+type Agents = Box<dyn Fn() -> Vec<u16> + Send + Sync>;
+
+struct Shared{
+    links : Mutex<HashMap<u16, Link>>,  // Link of every agent, by the port of the agent
+    default : Mutex<Link>,              // Link of the agents added later, set with the target "all"
+    rng : Mutex<StdRng>,
+    running : AtomicBool,               // Cleared to stop the proxy
+}
+
+struct Front{
+    port : u16,                         // Port the proxy listens on for the agent
+    open : Arc<AtomicBool>,             // Cleared to close the front once its agent left
+    thread : thread::JoinHandle<()>,    // Accepting thread
+}
+
+impl Front {
+    fn close(self) {
+        /*
+            Stops accepting connections and waits for the accepting thread.
+        */
+        self.open.store(false, Ordering::SeqCst);
+        // Wakes the accepting thread up
+        let _ = TcpStream::connect(SocketAddr::from(([127, 0, 0, 1], self.port)));
+        let _ = self.thread.join();
+    }
+}
+
+pub struct Proxy{
+    agents : Agents,                    // Returns the ports of the agents
+    shared : Arc<Shared>,
+    fronts : Mutex<HashMap<u16, Front>>,            // Front of every agent, by the port of the agent
+    groups : Mutex<HashMap<String, Vec<usize>>>,    // Agents of every group
+}
+
+fn forward(agent : u16, mut client : TcpStream, shared : &Shared) -> io::Result<()> {
+    /*
+        Forwards a request of the client to the agent listening on port agent and its reply back,
+        applying the faults of its link. The client closes its side of the connection once its
+        request is sent.
+    */
+    let link = shared.links.lock().expect("Proxy lock poisoned").get(&agent).cloned().unwrap_or_default();
+    if link.partitioned {
+        return Ok(());
+    }
+
+    let mut request = Vec::new();
+    client.read_to_end(&mut request)?;
+    let (delay, dropped) = {
+        let mut rng = shared.rng.lock().expect("Proxy lock poisoned");
+        (link.delay(&mut *rng), rng.gen_bool(link.drop))
+    };
+    thread::sleep(delay);
+    if dropped {
+        debug!("Proxy: dropped request to agent {}", agent);
+        return Ok(());
+    }
+
+    let mut stream = TcpStream::connect(SocketAddr::from(([127, 0, 0, 1], agent)))?;
+    stream.write_all(&request)?;
+    stream.shutdown(Shutdown::Write)?;
+    let mut reply = Vec::new();
+    stream.read_to_end(&mut reply)?;
+
+    let (delay, truncated) = {
+        let mut rng = shared.rng.lock().expect("Proxy lock poisoned");
+        let truncated = !reply.is_empty() && rng.gen_bool(link.truncate);
+        if truncated {
+            let len = rng.gen_range(0..reply.len());
+            reply.truncate(len);
+        }
+        (link.delay(&mut *rng), truncated)
+    };
+    if truncated {
+        debug!("Proxy: truncated reply of agent {} to {} byte(s)", agent, reply.len());
+    }
+    thread::sleep(delay);
+    client.write_all(&reply)
+}
+
+fn parse_indices(spec : &str) -> Result<Vec<usize>, String> {
+    /*
+        Parses a comma separated list of agent indices and ranges, e.g. 0,2,5-7.
+    */
+    let invalid = || format!("agents should be a comma separated list of indices or ranges like 0,2,5-7, got {}", spec);
+    let mut indices = Vec::new();
+    for item in spec.split(',') {
+        match item.split_once('-') {
+            Some((first, last)) => {
+                let first : usize = first.parse().map_err(|_| invalid())?;
+                let last : usize = last.parse().map_err(|_| invalid())?;
+                indices.extend(first..=last);
+            },
+            None => indices.push(item.parse().map_err(|_| invalid())?),
+        }
+    }
+    Ok(indices)
+}
+
+impl Proxy {
+    pub fn start<F>(agents : F, seed : u64) -> io::Result<Self>
+    where F : Fn() -> Vec<u16> + Send + Sync + 'static {
+        /*
+            Args :
+                - agents : returns the ports of the agents, e.g. Network::ports()
+                - seed : seed of the faults' random number generator
+            Returns :
+                - Proxy, forwarding without any fault, or the error met opening a front port
+        */
+        let proxy = Self {
+            agents : Box::new(agents),
+            shared : Arc::new(Shared {
+                links : Mutex::new(HashMap::new()),
+                default : Mutex::new(Link::default()),
+                rng : Mutex::new(StdRng::seed_from_u64(seed)),
+                running : AtomicBool::new(true),
+            }),
+            fronts : Mutex::new(HashMap::new()),
+            groups : Mutex::new(HashMap::new()),
+        };
+        proxy.ports()?;
+        Ok(proxy)
+    }
+
+    fn open(&self, agent : u16) -> io::Result<Front> {
+        /*
+            Opens a front port forwarding to the agent listening on port agent, with the link of "all".
+        */
+        let listener = TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 0)))?;
+        let port = listener.local_addr()?.port();
+        let default = self.shared.default.lock().expect("Proxy lock poisoned").clone();
+        self.shared.links.lock().expect("Proxy lock poisoned").entry(agent).or_insert(default);
+
+        let open = Arc::new(AtomicBool::new(true));
+        let shared = Arc::clone(&self.shared);
+        let thread = {
+            let open = Arc::clone(&open);
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    if !shared.running.load(Ordering::SeqCst) || !open.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    match stream {
+                        Ok(stream) => {
+                            let shared = Arc::clone(&shared);
+                            thread::spawn(move || {
+                                if let Err(e) = forward(agent, stream, &shared) {
+                                    warn!("Proxy: failed to forward to agent {} : {}", agent, e);
+                                }
+                            });
+                        },
+                        Err(e) => warn!("Proxy: connection failed : {}", e),
+                    }
+                }
+            })
+        };
+        Ok(Front { port, open, thread })
+    }
+
+    pub fn ports(&self) -> io::Result<Vec<u16>> {
+        /*
+            Returns the front port of every agent, to give to the client instead of the agents' ports.
+            Opens the front ports of the agents added since the last call and closes those of the
+            agents which left.
+
+            Returns :
+                - Vec<u16> : front port of every agent, or the error met opening a front port
+        */
+        let agents = (self.agents)();
+        let mut fronts = self.fronts.lock().expect("Proxy lock poisoned");
+        let gone : Vec<u16> = fronts.keys().filter(|agent| !agents.contains(agent)).copied().collect();
+        for agent in gone {
+            if let Some(front) = fronts.remove(&agent) {
+                self.shared.links.lock().expect("Proxy lock poisoned").remove(&agent);
+                front.close();
+            }
+        }
+        for &agent in &agents {
+            if let Entry::Vacant(entry) = fronts.entry(agent) {
+                entry.insert(self.open(agent)?);
+            }
+        }
+        Ok(agents.iter().map(|agent| fronts[agent].port).collect())
+    }
+
+    pub fn group(&self, name : &str, agents : &str) -> Result<(), String> {
+        /*
+            Defines a group of agents which can be configured at once.
+
+            Args :
+                - name : name of the group, neither "all" nor a number
+                - agents : comma separated list of indices and ranges, e.g. 0,2,5-7
+        */
+        if name == "all" || name.parse::<usize>().is_ok() {
+            return Err(format!("a group cannot be named {}", name));
+        }
+        let indices = parse_indices(agents)?;
+        self.groups.lock().expect("Proxy lock poisoned").insert(name.to_string(), indices);
+        Ok(())
+    }
+
+    pub fn configure(&self, target : &str, settings : &[&str]) -> Result<(), String> {
+        /*
+            Changes the links of the target agents, see Link::apply(). Nothing is changed if a setting
+            or the target is invalid.
+
+            Args :
+                - target : "all", the index of an agent or the name of a group
+                - settings : changes to apply in order
+        */
+        let mut checked = Link::default();
+        for setting in settings {
+            checked.apply(setting)?;
+        }
+        let agents = (self.agents)();
+        let indices = match target {
+            "all" => (0..agents.len()).collect(),
+            _ => match target.parse::<usize>() {
+                Ok(index) => vec![index],
+                Err(_) => self.groups.lock().expect("Proxy lock poisoned").get(target).cloned()
+                    .ok_or_else(|| format!("target should be all, an agent index or a group, got {}", target))?,
+            },
+        };
+        if let Some(index) = indices.iter().find(|&&index| index >= agents.len()) {
+            return Err(format!("there is no agent {}, the agents are numbered from 0 to {}", index, agents.len().saturating_sub(1)));
+        }
+
+        let mut default = self.shared.default.lock().expect("Proxy lock poisoned");
+        let mut links = self.shared.links.lock().expect("Proxy lock poisoned");
+        if target == "all" {
+            for setting in settings {
+                default.apply(setting)?;
+            }
+        }
+        for index in indices {
+            let link = links.entry(agents[index]).or_insert_with(|| default.clone());
+            for setting in settings {
+                link.apply(setting)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn links(&self) -> BTreeMap<usize, Link> {
+        /*
+            Returns the link of every agent, by index.
+        */
+        let default = self.shared.default.lock().expect("Proxy lock poisoned");
+        let links = self.shared.links.lock().expect("Proxy lock poisoned");
+        (self.agents)().iter().enumerate()
+            .map(|(index, agent)| (index, links.get(agent).cloned().unwrap_or_else(|| default.clone())))
+            .collect()
+    }
+
+    pub fn print_links(&self) {
+        /*
+            Prints the links which have faults, for the "net" command.
+        */
+        let ports = (self.agents)();
+        let default = Link::default();
+        let mut faulty = 0;
+        for (index, link) in self.links() {
+            if link != default {
+                faulty += 1;
+                println!("Agent {} (port {}) : {}", index, ports.get(index).cloned().unwrap_or_default(), link);
+            }
+        }
+        for (name, indices) in self.groups.lock().expect("Proxy lock poisoned").iter() {
+            println!("Group {} : agents {:?}", name, indices);
+        }
+        println!("{} of {} link(s) with faults", faulty, ports.len());
+    }
+
+    pub fn stop(&self) {
+        /*
+            Closes the front ports and waits for their threads.
+        */
+        self.shared.running.store(false, Ordering::SeqCst);
+        let fronts : Vec<Front> = self.fronts.lock().expect("Proxy lock poisoned").drain().map(|(_, front)| front).collect();
+        for front in fronts {
+            front.close();
+        }
+    }
+}
+
+
+/*---------------------------- TESTS ----------------------------*/
+
+#[cfg(test)]
+mod tests {
+    use crate::client::Client;
+    use crate::network::Network;
+    use crate::proxy::Proxy;
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_proxy() {
+        let values = BTreeMap::from([(String::new(), 3_u16)]);
+        let network = Arc::new(Network::spawn(values, 10, 5, 0.2, 42).unwrap());
+        let proxy = {
+            let network = Arc::clone(&network);
+            Arc::new(Proxy::start(move || network.ports(), 42).unwrap())
+        };
+        let client = {
+            let proxy = Arc::clone(&proxy);
+            Client::<u16>::new(0.2, move || proxy.ports().unwrap())
+        };
+        let answers = || client.query(&client.agents(), "").len();
+
+        assert_eq!(answers(), 5);
+
+        proxy.configure("0", &["partition"]).unwrap();
+        proxy.group("pair", "1-2").unwrap();
+        proxy.configure("pair", &["drop=1"]).unwrap();
+        assert_eq!(answers(), 2);
+
+        proxy.configure("all", &["reset", "truncate=1"]).unwrap();
+        assert_eq!(answers(), 0);
+
+        proxy.configure("all", &["reset", "latency=100ms", "jitter=10"]).unwrap();
+        let start = Instant::now();
+        assert_eq!(answers(), 5);
+        assert!(start.elapsed() >= Duration::from_millis(180));
+
+        // Agents added later get the links of "all"
+        network.extend(1);
+        assert_eq!(proxy.links()[&5].latency, Duration::from_millis(100));
+        assert!(proxy.configure("6", &["heal"]).is_err());
+
+        // A bad setting changes no link
+        assert!(proxy.configure("all", &["reset", "latency=fast"]).is_err());
+        assert!(proxy.links().values().all(|link| link.latency == Duration::from_millis(100)));
+
+        // A link follows its agent when the agents before it leave
+        proxy.configure("all", &["reset"]).unwrap();
+        proxy.configure("1", &["partition"]).unwrap();
+        network.kill(network.ports()[0]);
+        assert!(proxy.links()[&0].partitioned);
+        assert_eq!(answers(), 4);
+
+        proxy.stop();
+        network.shutdown();
+    }
+}
@@ -0,0 +1,129 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io;
+
+/*
+    Track record of the agents across games, kept in a JSON file keyed by the identity of every agent
+    (see Agent::identity), not by its port which changes with every game and every restart :
+        {"2f8a9c0e11d4b7a3":{"games":3,"penalties":2}, ...}
+    The identities are kept from one game to the next : a new game gives the identities of the file
+    to its agents, those with the most games first (see roster()). The client loads the penalties
+    at the start of the game, and every agent it identified during the game is added its new
+    penalties once the game ends.
+*/
+
+pub const REPUTATION_FILE : &str = "liarslie_reputation.json";     // Default reputation file, LIARSLIE_REPUTATION overrides it
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Record{
+    pub games : usize,                  // Games in which the client queried the agent
+    pub penalties : usize,              // Rejected values the agent answered over these games
+}
+
+pub type Records = BTreeMap<String, Record>;
+
+pub fn reputation_file() -> String {
+    /*
+        Returns the reputation file : $LIARSLIE_REPUTATION or REPUTATION_FILE.
+    */
+    env::var("LIARSLIE_REPUTATION").unwrap_or_else(|_| REPUTATION_FILE.to_string())
+}
+
+pub fn load(path : &str) -> io::Result<Records> {
+    /*
+        Reads the record of every agent, none if the file does not exist yet.
+    */
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Records::new()),
+        Err(e) => return Err(e),
+    };
+    serde_json::from_str(&content).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{} : {}", path, e)))
+}
+
+pub fn save(path : &str, records : &Records) -> io::Result<()> {
+    /*
+        Writes the record of every agent, through a temporary file so that an interrupted write
+        does not lose the previous records.
+    */
+    let tmp = format!("{}.tmp", path);
+    fs::write(&tmp, serde_json::to_string(records).map_err(io::Error::other)?)?;
+    fs::rename(&tmp, path)
+}
+
+pub fn roster(records : &Records, num_agents : usize) -> Vec<String> {
+    /*
+        Returns the identities given to the agents of a new game, at most num_agents : the agents with
+        the most games first, so that the same agents come back whatever the number of agents, ties
+        broken by identity.
+    */
+    let mut identities : Vec<(&String, &Record)> = records.iter().collect();
+    identities.sort_by(|(a, ra), (b, rb)| rb.games.cmp(&ra.games).then(a.cmp(b)));
+    identities.into_iter().take(num_agents).map(|(identity, _)| identity.clone()).collect()
+}
+
+pub fn penalties(records : &Records) -> BTreeMap<String, f32> {
+    /*
+        Returns the penalties per game of every agent, as given to Client::set_track_record() : an
+        agent is judged on its average game, so that an honest agent recovers from a bad game
+        instead of piling up penalties forever.
+    */
+    records.iter()
+        .map(|(identity, record)| (identity.clone(), record.penalties as f32 / record.games.max(1) as f32))
+        .collect()
+}
+
+pub fn merge(records : &mut Records, game : &BTreeMap<String, usize>) {
+    /*
+        Adds a game to the records.
+
+        Args :
+            - records : record of every agent before the game
+            - game : penalties of every agent identified during the game, see Client::game_penalties()
+    */
+    for (identity, penalties) in game {
+        let record = records.entry(identity.clone()).or_default();
+        record.games += 1;
+        record.penalties += penalties;
+    }
+}
+
+
+/*---------------------------- TESTS ----------------------------*/
+
+#[cfg(test)]
+mod tests {
+    use crate::reputation::{Record, load, merge, penalties, roster, save};
+    use std::collections::BTreeMap;
+    use std::fs;
+
+    #[test]
+    fn test_reputation_file() {
+        let path = std::env::temp_dir().join(format!("liarslie_test_reputation_{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+        let mut records = load(path).unwrap();
+        assert!(records.is_empty());
+
+        merge(&mut records, &BTreeMap::from([("a".to_string(), 1), ("b".to_string(), 0)]));
+        save(path, &records).unwrap();
+        let mut records = load(path).unwrap();
+        merge(&mut records, &BTreeMap::from([("a".to_string(), 2)]));
+        save(path, &records).unwrap();
+
+        let records = load(path).unwrap();
+        assert_eq!(records["a"], Record { games : 2, penalties : 3 });
+        assert_eq!(records["b"], Record { games : 1, penalties : 0 });
+        assert_eq!(penalties(&records), BTreeMap::from([("a".to_string(), 1.5), ("b".to_string(), 0.)]));
+
+        // The agents with the most games come back first
+        assert_eq!(roster(&records, 1), vec!["a"]);
+        assert_eq!(roster(&records, 5), vec!["a", "b"]);
+
+        fs::write(path, "{").unwrap();
+        assert!(load(path).is_err());
+        fs::remove_file(path).unwrap();
+    }
+}
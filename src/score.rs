@@ -0,0 +1,216 @@
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use crate::client::Round;
+use crate::value::NetworkValue;
+
+/*
+    Scores of the games won, kept in a JSON lines file, one line per game :
+        {"player":"alice","difficulty":"hard","rounds":2,"queries":20,"millis":3150,"timestamp":1760000000}
+    A score is better with fewer rounds, then fewer agents queried, then less time.
+*/
+
+pub const SCORES_FILE : &str = "liarslie_scores.jsonl";             // Default scores file, LIARSLIE_SCORES overrides it
+pub const TOP : usize = 10;                                          // Number of scores shown by the leaderboard
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Difficulty{
+    Easy,                               // Less than a third of liars, the agents can agree among themselves
+    Medium,                             // Less than half of liars, the truth is still the majority
+    Hard,                               // At least half of liars
+}
+
+impl Difficulty {
+    pub fn of(liar_ratio : f32) -> Self {
+        if liar_ratio < 1. / 3. {
+            Difficulty::Easy
+        } else if liar_ratio < 0.5 {
+            Difficulty::Medium
+        } else {
+            Difficulty::Hard
+        }
+    }
+}
+
+impl FromStr for Difficulty {
+    type Err = String;
+
+    fn from_str(s : &str) -> Result<Self, Self::Err> {
+        match s {
+            "easy" => Ok(Difficulty::Easy),
+            "medium" => Ok(Difficulty::Medium),
+            "hard" => Ok(Difficulty::Hard),
+            _ => Err(format!("difficulty should be easy, medium or hard, got {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Difficulty {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Difficulty::Easy => write!(f, "easy"),
+            Difficulty::Medium => write!(f, "medium"),
+            Difficulty::Hard => write!(f, "hard"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Score{
+    pub player : String,
+    pub difficulty : Difficulty,
+    pub rounds : usize,                 // Rounds played until the value was found
+    pub queries : usize,                // Agents queried over every round
+    pub millis : u64,                   // Time from the start of the game to the win
+    pub timestamp : u64,                // Unix time of the win, in seconds
+}
+
+impl Score {
+    pub fn new<V : NetworkValue>(player : &str, liar_ratio : f32, history : &[Round<V>], elapsed : Duration) -> Self {
+        /*
+            Scores a game won.
+
+            Args :
+                - player : name of the player
+                - liar_ratio : ratio of liars of the network, gives the difficulty
+                - history : every round played
+                - elapsed : time from the start of the game to the win
+            Returns :
+                - Score
+        */
+        Self {
+            player : player.to_string(),
+            difficulty : Difficulty::of(liar_ratio),
+            rounds : history.len(),
+            queries : history.iter().map(|round| round.agents).sum(),
+            millis : elapsed.as_millis() as u64,
+            timestamp : SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+        }
+    }
+
+    fn rank(&self) -> (usize, usize, u64) {
+        (self.rounds, self.queries, self.millis)
+    }
+}
+
+pub fn scores_file() -> String {
+    /*
+        Returns the scores file : $LIARSLIE_SCORES or SCORES_FILE.
+    */
+    env::var("LIARSLIE_SCORES").unwrap_or_else(|_| SCORES_FILE.to_string())
+}
+
+pub fn save(path : &str, score : &Score) -> io::Result<()> {
+    /*
+        Appends a score to the scores file, creating it if needed.
+    */
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", serde_json::to_string(score).map_err(io::Error::other)?)
+}
+
+pub fn load(path : &str) -> io::Result<Vec<Score>> {
+    /*
+        Reads every score of the scores file, none if it does not exist yet.
+    */
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    content.lines().filter(|line| !line.trim().is_empty()).enumerate().map(|(number, line)| {
+        serde_json::from_str(line).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("line {} of {} : {}", number + 1, path, e)))
+    }).collect()
+}
+
+pub fn leaderboard(scores : &[Score], difficulty : Difficulty, top : usize) -> Vec<Score> {
+    /*
+        Returns the best scores of a difficulty, best first, keeping only the best score of every player.
+
+        Args :
+            - scores : every score saved
+            - difficulty : difficulty of the leaderboard
+            - top : maximum number of scores returned
+        Returns :
+            - Vec<Score> : best scores, ties broken by the oldest
+    */
+    let mut best : Vec<Score> = Vec::new();
+    let mut sorted : Vec<&Score> = scores.iter().filter(|s| s.difficulty == difficulty).collect();
+    sorted.sort_by_key(|s| (s.rank(), s.timestamp));
+    for score in sorted {
+        if !best.iter().any(|b| b.player == score.player) {
+            best.push(score.clone());
+        }
+    }
+    best.truncate(top);
+    best
+}
+
+pub fn print_leaderboard(path : &str, difficulty : Option<Difficulty>) {
+    /*
+        Prints the leaderboard of a difficulty, or of every difficulty, for the "leaderboard" command.
+    */
+    let scores = match load(path) {
+        Ok(scores) => scores,
+        Err(e) => {
+            println!("Unable to read the scores in {} : {}", path, e);
+            return;
+        }
+    };
+    let difficulties = match difficulty {
+        Some(difficulty) => vec![difficulty],
+        None => vec![Difficulty::Easy, Difficulty::Medium, Difficulty::Hard],
+    };
+    for difficulty in difficulties {
+        let best = leaderboard(&scores, difficulty, TOP);
+        println!("Leaderboard {} : {} score(s)", difficulty, best.len());
+        for (rank, score) in best.iter().enumerate() {
+            println!("{:>3}. {:<16} {:>3} round(s) {:>6} queries {:>8.1} s", rank + 1, score.player, score.rounds, score.queries, score.millis as f64 / 1000.);
+        }
+    }
+}
+
+
+/*---------------------------- TESTS ----------------------------*/
+
+#[cfg(test)]
+mod tests {
+    use crate::client::Round;
+    use crate::score::{Difficulty, Score, leaderboard, load, save};
+    use std::fs;
+    use std::time::Duration;
+
+    #[test]
+    fn test_score() {
+        assert_eq!(Difficulty::of(0.2), Difficulty::Easy);
+        assert_eq!(Difficulty::of(0.4), Difficulty::Medium);
+        assert_eq!(Difficulty::of(0.5), Difficulty::Hard);
+        assert_eq!("hard".parse::<Difficulty>(), Ok(Difficulty::Hard));
+
+        let round = |round| Round::<u16> { round, key : String::new(), agents : 10, responses : Vec::new(), guess : None, epoch : 0 };
+        let score = Score::new("alice", 0.5, &[round(1), round(2)], Duration::from_millis(1500));
+        assert_eq!((score.rounds, score.queries, score.millis), (2, 20, 1500));
+
+        let path = std::env::temp_dir().join(format!("liarslie_test_scores_{}.jsonl", std::process::id()));
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+        assert!(load(path).unwrap().is_empty());
+        save(path, &score).unwrap();
+        save(path, &Score { rounds : 1, queries : 10, ..score.clone() }).unwrap();
+        save(path, &Score { player : "bob".to_string(), rounds : 1, queries : 5, ..score.clone() }).unwrap();
+        save(path, &Score { player : "carol".to_string(), difficulty : Difficulty::Easy, ..score.clone() }).unwrap();
+        let scores = load(path).unwrap();
+        assert_eq!(scores.len(), 4);
+
+        // Only the best score of every player, the fewest rounds then the fewest queries first
+        let best = leaderboard(&scores, Difficulty::Hard, 10);
+        assert_eq!(best.iter().map(|s| (s.player.as_str(), s.queries)).collect::<Vec<_>>(), vec![("bob", 5), ("alice", 10)]);
+        assert_eq!(leaderboard(&scores, Difficulty::Hard, 1).len(), 1);
+        assert_eq!(leaderboard(&scores, Difficulty::Medium, 10).len(), 0);
+        fs::remove_file(path).unwrap();
+    }
+}
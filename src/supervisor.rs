@@ -0,0 +1,307 @@
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+use log::{debug, info, warn};
+use std::collections::HashSet;
+use crate::agent::Agent;
+use crate::turncoat::Turn;
+use crate::value::NetworkValue;
+
+pub const WATCH_INTERVAL : Duration = Duration::from_millis(100);    // Time between two checks of the agents' threads
+
+#[derive(Clone)]
+pub struct AgentSpec<V : NetworkValue>{
+    pub keys : Vec<(String, V, bool)>,  // (key, real value, true if the agent lies about it), see Agent::new
+    pub seed : u64,                     // Seed of the agent, the same seed gives the same lies
+    pub turn : Option<(Turn, HashSet<String>)>,     // When the agent changes sides on these keys, see Agent::set_turn
+    pub identity : String,              // Identity of the agent, the same for every incarnation, see Agent::identity
+}
+
+struct Slot<V : NetworkValue>{
+    spec : AgentSpec<V>,
+    port : u16,                         // Port of the current incarnation of the agent
+    thread : Option<thread::JoinHandle<()>>,    // None once the agent is stopped and joined
+    restarts : u32,                     // Number of times the agent was restarted
+    paused : bool,                      // Stopped on purpose, kept to be revived later
+}
+
+type Registry = Arc<dyn Fn(Vec<u16>) + Send + Sync>;
+
+pub struct Supervisor<V : NetworkValue>{
+    slots : Arc<Mutex<Vec<Slot<V>>>>,
+    max_value : V,
+    registry : Registry,                // Called with the ports of all the agents when they change
+    running : Arc<AtomicBool>,          // Cleared to stop watching the agents
+    watcher : Mutex<Option<thread::JoinHandle<()>>>,
+}
+
+fn launch<V : NetworkValue>(spec : &AgentSpec<V>, max_value : &V) -> (u16, thread::JoinHandle<()>) {
+    /*
+        Creates an agent from its spec and runs it on a new thread.
+
+        Returns :
+            - u16 : port of the agent
+            - thread::JoinHandle<()> : thread of the agent
+    */
+    let mut agent = Agent::new(spec.keys.clone(), max_value, spec.seed);
+    agent.identity = spec.identity.clone();
+    if let Some((turn, keys)) = &spec.turn {
+        agent.set_turn(*turn, keys.clone());
+    }
+    let port = agent.id;
+    let thread = thread::spawn(move || {agent.run();});
+    (port, thread)
+}
+
+impl<V : NetworkValue> Supervisor<V>{
+    pub fn start<F>(specs : Vec<AgentSpec<V>>, max_value : V, registry : F) -> Self
+    where F : Fn(Vec<u16>) + Send + Sync + 'static {
+        /*
+            Launches an agent per spec and a thread watching them. An agent whose thread has panicked
+            is launched again with the same spec on a new port, then registry is called with the
+            new ports of all the agents.
+
+            Args :
+                - specs : spec of every agent
+                - max_value : maximum value
+                - registry : called with the ports of all the agents when they are launched, added, removed or restarted
+            Returns :
+                - Supervisor
+        */
+        let slots : Vec<Slot<V>> = specs.into_iter().map(|spec| {
+            let (port, thread) = launch(&spec, &max_value);
+            Slot { spec, port, thread : Some(thread), restarts : 0, paused : false }
+        }).collect();
+        registry(slots.iter().map(|slot| slot.port).collect());
+
+        let slots = Arc::new(Mutex::new(slots));
+        let running = Arc::new(AtomicBool::new(true));
+        let registry : Registry = Arc::new(registry);
+
+        let watcher = {
+            let slots = Arc::clone(&slots);
+            let running = Arc::clone(&running);
+            let registry = Arc::clone(&registry);
+            let max_value = max_value.clone();
+            thread::spawn(move || {
+                while running.load(Ordering::SeqCst) {
+                    Self::restart_crashed(&slots, &max_value, &registry);
+                    thread::sleep(WATCH_INTERVAL);
+                }
+            })
+        };
+
+        Self {
+            slots,
+            max_value,
+            registry,
+            running,
+            watcher : Mutex::new(Some(watcher)),
+        }
+    }
+
+    pub fn add(&self, specs : Vec<AgentSpec<V>>) -> Vec<u16> {
+        /*
+            Launches an agent per spec and supervises it.
+
+            Args :
+                - specs : spec of every new agent
+            Returns :
+                - Vec<u16> : ports of the new agents
+        */
+        let mut slots = self.slots.lock().expect("Supervisor lock poisoned");
+        let mut ports = Vec::new();
+        for spec in specs {
+            let (port, thread) = launch(&spec, &self.max_value);
+            ports.push(port);
+            slots.push(Slot { spec, port, thread : Some(thread), restarts : 0, paused : false });
+        }
+        (self.registry)(slots.iter().map(|slot| slot.port).collect());
+        ports
+    }
+
+    pub fn remove(&self, port : u16) -> Option<thread::JoinHandle<()>> {
+        /*
+            Stops supervising the agent listening on port. The agent is not stopped.
+
+            Args :
+                - port : port of the agent
+            Returns :
+                - Option<thread::JoinHandle<()>> : thread of the agent if it is still running, None if
+                  no agent listens on port
+        */
+        let mut slots = self.slots.lock().expect("Supervisor lock poisoned");
+        let index = slots.iter().position(|slot| slot.port == port)?;
+        let slot = slots.remove(index);
+        (self.registry)(slots.iter().map(|slot| slot.port).collect());
+        slot.thread
+    }
+
+    pub fn pause(&self, port : u16) -> Option<thread::JoinHandle<()>> {
+        /*
+            Stops supervising the agent listening on port but keeps its spec, see revive(). The agent is not stopped.
+
+            Args :
+                - port : port of the agent
+            Returns :
+                - Option<thread::JoinHandle<()>> : thread of the agent, None if no running agent listens on port
+        */
+        let mut slots = self.slots.lock().expect("Supervisor lock poisoned");
+        let slot = slots.iter_mut().find(|slot| slot.port == port && !slot.paused)?;
+        slot.paused = true;
+        slot.thread.take()
+    }
+
+    pub fn revive(&self, port : u16) -> Option<u16> {
+        /*
+            Launches again a paused agent with the same spec on a new port.
+
+            Args :
+                - port : port of the paused agent
+            Returns :
+                - Option<u16> : new port of the agent, None if no paused agent listened on port
+        */
+        let mut slots = self.slots.lock().expect("Supervisor lock poisoned");
+        let slot = slots.iter_mut().find(|slot| slot.port == port && slot.paused)?;
+        let (new_port, thread) = launch(&slot.spec, &self.max_value);
+        slot.port = new_port;
+        slot.thread = Some(thread);
+        slot.paused = false;
+        (self.registry)(slots.iter().map(|slot| slot.port).collect());
+        Some(new_port)
+    }
+
+    fn restart_crashed(slots : &Mutex<Vec<Slot<V>>>, max_value : &V, registry : &Registry) {
+        /*
+            Joins the finished threads and launches again the agents which have panicked.
+        */
+        let mut slots = slots.lock().expect("Supervisor lock poisoned");
+        let mut restarted = false;
+
+        for slot in slots.iter_mut() {
+            if !slot.thread.as_ref().is_some_and(|thread| thread.is_finished()) {
+                continue;
+            }
+            let thread = slot.thread.take().expect("The thread is finished");
+            match thread.join() {
+                Ok(_) => debug!("Agent {} stopped", slot.port),
+                Err(_) => {
+                    let (port, thread) = launch(&slot.spec, max_value);
+                    slot.restarts += 1;
+                    warn!("Agent {} has crashed, restarted on port {} ({} restart(s))", slot.port, port, slot.restarts);
+                    slot.port = port;
+                    slot.thread = Some(thread);
+                    restarted = true;
+                }
+            }
+        }
+
+        if restarted {
+            registry(slots.iter().map(|slot| slot.port).collect());
+        }
+    }
+
+    pub fn ports(&self) -> Vec<u16> {
+        /*
+            Returns the current port of every agent.
+        */
+        self.slots.lock().expect("Supervisor lock poisoned").iter().map(|slot| slot.port).collect()
+    }
+
+    pub fn paused(&self) -> Vec<u16> {
+        /*
+            Returns the port of every paused agent.
+        */
+        self.slots.lock().expect("Supervisor lock poisoned").iter().filter(|slot| slot.paused).map(|slot| slot.port).collect()
+    }
+
+    pub fn restarts(&self) -> Vec<(u16, u32)> {
+        /*
+            Returns (current port, number of restarts) of every agent restarted at least once.
+        */
+        self.slots.lock().expect("Supervisor lock poisoned").iter()
+            .filter(|slot| slot.restarts > 0)
+            .map(|slot| (slot.port, slot.restarts))
+            .collect()
+    }
+
+    pub fn stop_watching(&self) {
+        /*
+            Stops the watching thread, crashed agents are not restarted anymore.
+        */
+        self.running.store(false, Ordering::SeqCst);
+        if let Some(watcher) = self.watcher.lock().expect("Supervisor lock poisoned").take() {
+            let _ = watcher.join();
+        }
+    }
+
+    pub fn shutdown(&self) {
+        /*
+            Stops watching and joins every agent's thread. The agents must have received "stop".
+        */
+        self.stop_watching();
+        let threads : Vec<(u16, thread::JoinHandle<()>)> = self.slots.lock().expect("Supervisor lock poisoned")
+            .iter_mut()
+            .filter_map(|slot| slot.thread.take().map(|thread| (slot.port, thread)))
+            .collect();
+        for (port, thread) in threads {
+            match thread.join() {
+                Ok(_) => debug!("Joined thread of agent {}", port),
+                Err(_) => warn!("The thread of agent {} has panicked", port),
+            }
+        }
+        info!("Joined all threads");
+    }
+}
+
+
+/*---------------------------- TESTS ----------------------------*/
+
+#[cfg(test)]
+mod tests {
+    use crate::supervisor::{AgentSpec, Supervisor};
+    use crate::value::NetworkValue;
+    use std::io::{Read, Write};
+    use std::net::{SocketAddr, TcpStream};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    fn talk(port : u16) -> Option<u16> {
+        let mut stream = TcpStream::connect(SocketAddr::from(([127, 0, 0, 1], port))).ok()?;
+        stream.write_all(b"talk").ok()?;
+        let mut buffer = Vec::new();
+        stream.read_to_end(&mut buffer).ok()?;
+        u16::decode(&buffer)
+    }
+
+    #[test]
+    fn test_supervisor() {
+        let registered = Arc::new(Mutex::new(Vec::new()));
+        let specs = vec![AgentSpec { keys : vec![(String::new(), 3_u16, true)], seed : 7, turn : None, identity : "7".to_string() }];
+        let supervisor = {
+            let registered = Arc::clone(&registered);
+            Supervisor::start(specs, 10, move |ports| *registered.lock().unwrap() = ports)
+        };
+
+        let port = supervisor.ports()[0];
+        assert_eq!(*registered.lock().unwrap(), vec![port]);
+        let lie = talk(port).unwrap();
+
+        // Invalid UTF-8 is dropped, "crash" makes the agent panic in the tests
+        TcpStream::connect(SocketAddr::from(([127, 0, 0, 1], port))).unwrap().write_all(&[0xff, 0xfe]).unwrap();
+        assert_eq!(talk(port), Some(lie));
+        TcpStream::connect(SocketAddr::from(([127, 0, 0, 1], port))).unwrap().write_all(b"crash").unwrap();
+        thread::sleep(Duration::from_millis(500));
+
+        let new_port = supervisor.ports()[0];
+        assert_ne!(new_port, port);
+        assert_eq!(*registered.lock().unwrap(), vec![new_port]);
+        assert_eq!(supervisor.restarts(), vec![(new_port, 1)]);
+        assert_eq!(talk(new_port), Some(lie));
+
+        TcpStream::connect(SocketAddr::from(([127, 0, 0, 1], new_port))).unwrap().write_all(b"stop").unwrap();
+        supervisor.shutdown();
+    }
+}
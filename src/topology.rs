@@ -0,0 +1,325 @@
+use rand::Rng;
+use rand::seq::SliceRandom;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs;
+use std::str::FromStr;
+
+/*
+    Who talks to whom among the agents. The graph is built over the agents' indices (their order of
+    creation), the network gives every agent the ports of its neighbors.
+*/
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum Topology{
+    #[default]
+    Complete,                           // Every agent is the neighbor of every other agent
+    Ring,                               // Agent i is the neighbor of agents i - 1 and i + 1
+    Regular(usize),                     // Random graph where every agent has k neighbors
+    SmallWorld(usize, f64),             // Watts-Strogatz : ring of k nearest neighbors, every edge rewired with probability p
+    File(String),                       // Edge list file, a line "i j" per edge between the agents i and j (from 0)
+}
+
+impl FromStr for Topology {
+    type Err = String;
+
+    fn from_str(s : &str) -> Result<Self, Self::Err> {
+        let usage = || format!("topology should be complete, ring, regular:<k>, small-world:<k>:<p> or file:<path>, got {}", s);
+        let parts : Vec<&str> = s.splitn(3, ':').collect();
+        match parts[..] {
+            ["complete"] => Ok(Topology::Complete),
+            ["ring"] => Ok(Topology::Ring),
+            ["regular", k] => Ok(Topology::Regular(k.parse().map_err(|_| usage())?)),
+            ["small-world", k, p] => Ok(Topology::SmallWorld(k.parse().map_err(|_| usage())?, p.parse().map_err(|_| usage())?)),
+            ["file", _] | ["file", _, _] => Ok(Topology::File(s["file:".len()..].to_string())),
+            _ => Err(usage()),
+        }
+    }
+}
+
+impl fmt::Display for Topology {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Topology::Complete => write!(f, "complete"),
+            Topology::Ring => write!(f, "ring"),
+            Topology::Regular(k) => write!(f, "regular:{}", k),
+            Topology::SmallWorld(k, p) => write!(f, "small-world:{}:{}", k, p),
+            Topology::File(path) => write!(f, "file:{}", path),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Graph{
+    adjacency : Vec<BTreeSet<usize>>,   // Neighbors of every agent
+}
+
+impl Graph {
+    pub fn empty(n : usize) -> Self {
+        Self { adjacency : vec![BTreeSet::new(); n] }
+    }
+
+    pub fn add_edge(&mut self, a : usize, b : usize) {
+        if a != b {
+            self.adjacency[a].insert(b);
+            self.adjacency[b].insert(a);
+        }
+    }
+
+    fn remove_edge(&mut self, a : usize, b : usize) {
+        self.adjacency[a].remove(&b);
+        self.adjacency[b].remove(&a);
+    }
+
+    pub fn len(&self) -> usize {
+        self.adjacency.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.adjacency.is_empty()
+    }
+
+    pub fn neighbors(&self, agent : usize) -> &BTreeSet<usize> {
+        &self.adjacency[agent]
+    }
+
+    pub fn edges(&self) -> usize {
+        self.adjacency.iter().map(|neighbors| neighbors.len()).sum::<usize>() / 2
+    }
+
+    pub fn degrees(&self) -> (usize, usize) {
+        /*
+            Returns the minimum and maximum number of neighbors of an agent.
+        */
+        let degrees = self.adjacency.iter().map(|neighbors| neighbors.len());
+        (degrees.clone().min().unwrap_or(0), degrees.max().unwrap_or(0))
+    }
+
+    pub fn remove(&mut self, agent : usize) {
+        /*
+            Removes an agent, the following agents' indices are shifted down by one.
+        */
+        self.adjacency.remove(agent);
+        for neighbors in self.adjacency.iter_mut() {
+            *neighbors = neighbors.iter()
+                .filter(|&&b| b != agent)
+                .map(|&b| if b > agent { b - 1 } else { b })
+                .collect();
+        }
+    }
+
+    pub fn grow<R : Rng>(&mut self, count : usize, rng : &mut R) {
+        /*
+            Adds count agents. In a complete graph they are connected to every agent, otherwise every
+            new agent is connected to as many random agents as the average number of neighbors.
+        */
+        let n = self.len();
+        let complete = self.edges() == n * n.saturating_sub(1) / 2;
+        let degree = (2 * self.edges() + n / 2).checked_div(n).unwrap_or(0);
+        for _ in 0..count {
+            let new = self.len();
+            self.adjacency.push(BTreeSet::new());
+            let neighbors : Vec<usize> = if complete {
+                (0..new).collect()
+            } else {
+                rand::seq::index::sample(rng, new, degree.min(new)).into_iter().collect()
+            };
+            for b in neighbors {
+                self.add_edge(new, b);
+            }
+        }
+    }
+}
+
+fn read_edges(path : &str, n : usize) -> Result<Graph, String> {
+    /*
+        Reads an edge list file : a line "i j" per edge, empty lines and lines starting with # are ignored.
+    */
+    let content = fs::read_to_string(path).map_err(|e| format!("unable to read topology file {} : {}", path, e))?;
+    let mut graph = Graph::empty(n);
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let invalid = || format!("line {} of {} should be an edge \"i j\" between two agents in [0 ; {}[, got {}", number + 1, path, n, line);
+        let agents : Vec<usize> = line.split_whitespace().map(|i| i.parse().map_err(|_| invalid())).collect::<Result<_, _>>()?;
+        match agents[..] {
+            [a, b] if a < n && b < n && a != b => graph.add_edge(a, b),
+            _ => return Err(invalid()),
+        }
+    }
+    Ok(graph)
+}
+
+fn random_regular<R : Rng>(n : usize, k : usize, rng : &mut R) -> Result<Graph, String> {
+    /*
+        Random k-regular graph : every agent has k slots, the slots are shuffled and paired up while
+        avoiding loops and double edges. Starts again if the last slots cannot be paired.
+    */
+    for _ in 0..100 {
+        let mut graph = Graph::empty(n);
+        let mut slots : Vec<usize> = (0..n).flat_map(|agent| std::iter::repeat_n(agent, k)).collect();
+        slots.shuffle(rng);
+        while let Some(a) = slots.pop() {
+            match slots.iter().position(|&b| b != a && !graph.adjacency[a].contains(&b)) {
+                Some(j) => {
+                    let b = slots.swap_remove(j);
+                    graph.add_edge(a, b);
+                },
+                None => break,
+            }
+        }
+        if graph.degrees() == (k, k) {
+            return Ok(graph);
+        }
+    }
+    Err(format!("unable to build a random {}-regular graph with {} agents", k, n))
+}
+
+fn small_world<R : Rng>(n : usize, k : usize, p : f64, rng : &mut R) -> Graph {
+    /*
+        Watts-Strogatz graph : every agent is connected to its k nearest agents on a ring, then
+        every edge is rewired to a random agent with probability p.
+    */
+    let mut graph = Graph::empty(n);
+    for a in 0..n {
+        for j in 1..=k / 2 {
+            graph.add_edge(a, (a + j) % n);
+        }
+    }
+    for a in 0..n {
+        for j in 1..=k / 2 {
+            let b = (a + j) % n;
+            if graph.adjacency[a].contains(&b) && graph.adjacency[a].len() < n - 1 && rng.gen_bool(p) {
+                let c = loop {
+                    let c = rng.gen_range(0..n);
+                    if c != a && !graph.adjacency[a].contains(&c) {
+                        break c;
+                    }
+                };
+                graph.remove_edge(a, b);
+                graph.add_edge(a, c);
+            }
+        }
+    }
+    graph
+}
+
+impl Topology {
+    pub fn check(&self, n : usize) -> Result<(), String> {
+        /*
+            Checks that the topology can be built with n agents.
+        */
+        match self {
+            Topology::Complete | Topology::Ring => Ok(()),
+            Topology::Regular(k) => {
+                if *k == 0 || *k >= n || (n * k) % 2 == 1 {
+                    return Err(format!("regular:<k> needs 0 < k < num-agents and k * num-agents even, got k = {}", k));
+                }
+                Ok(())
+            },
+            Topology::SmallWorld(k, p) => {
+                if *k < 2 || *k >= n || k % 2 == 1 {
+                    return Err(format!("small-world:<k>:<p> needs an even k in [2 ; num-agents[, got k = {}", k));
+                }
+                if !(0. ..=1.).contains(p) {
+                    return Err(format!("small-world:<k>:<p> needs p in [0 ; 1], got p = {}", p));
+                }
+                Ok(())
+            },
+            Topology::File(path) => read_edges(path, n).map(|_| ()),
+        }
+    }
+
+    pub fn build<R : Rng>(&self, n : usize, rng : &mut R) -> Result<Graph, String> {
+        /*
+            Builds the graph of n agents.
+
+            Args :
+                - n : number of agents
+                - rng : draws the random graphs
+            Returns :
+                - Graph : neighbors of every agent, or a message explaining why it cannot be built
+        */
+        self.check(n)?;
+        let mut graph = Graph::empty(n);
+        match self {
+            Topology::Complete => {
+                for a in 0..n {
+                    for b in a + 1..n {
+                        graph.add_edge(a, b);
+                    }
+                }
+            },
+            Topology::Ring => {
+                for a in 0..n {
+                    graph.add_edge(a, (a + 1) % n);
+                }
+            },
+            Topology::Regular(k) => graph = random_regular(n, *k, rng)?,
+            Topology::SmallWorld(k, p) => graph = small_world(n, *k, *p, rng),
+            Topology::File(path) => graph = read_edges(path, n)?,
+        }
+        Ok(graph)
+    }
+}
+
+
+/*---------------------------- TESTS ----------------------------*/
+
+#[cfg(test)]
+mod tests {
+    use crate::topology::Topology;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+    use std::fs;
+
+    #[test]
+    fn test_topology() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let build = |spec : &str, n : usize, rng : &mut StdRng| spec.parse::<Topology>().unwrap().build(n, rng);
+
+        let complete = build("complete", 10, &mut rng).unwrap();
+        assert_eq!(complete.edges(), 45);
+        assert_eq!(complete.degrees(), (9, 9));
+
+        let ring = build("ring", 10, &mut rng).unwrap();
+        assert_eq!(ring.edges(), 10);
+        assert!(ring.neighbors(0).contains(&9));
+
+        let regular = build("regular:4", 20, &mut rng).unwrap();
+        assert_eq!(regular.degrees(), (4, 4));
+        assert!(build("regular:3", 9, &mut rng).is_err());
+
+        // Without rewiring, a small world is a ring of nearest neighbors
+        let lattice = build("small-world:4:0", 10, &mut rng).unwrap();
+        assert_eq!(lattice.edges(), 20);
+        assert!(lattice.neighbors(0).contains(&8));
+        assert_eq!(build("small-world:4:0.5", 10, &mut rng).unwrap().edges(), 20);
+
+        let path = std::env::temp_dir().join(format!("liarslie_test_topology_{}.txt", std::process::id()));
+        fs::write(&path, "# star\n0 1\n0 2\n\n0 3\n").unwrap();
+        let star = build(&format!("file:{}", path.display()), 4, &mut rng).unwrap();
+        assert_eq!(star.degrees(), (1, 3));
+        assert!(build(&format!("file:{}", path.display()), 3, &mut rng).is_err());
+        fs::remove_file(&path).unwrap();
+
+        // Removing an agent shifts the following ones, new agents are connected like the others
+        let mut ring = ring;
+        ring.remove(0);
+        assert_eq!(ring.len(), 9);
+        assert_eq!(ring.edges(), 8);
+        assert!(ring.neighbors(0).contains(&1));
+        ring.grow(2, &mut rng);
+        assert_eq!(ring.len(), 11);
+        assert_eq!(ring.neighbors(10).len(), 2);
+
+        let mut complete = complete;
+        complete.grow(1, &mut rng);
+        assert_eq!(complete.degrees(), (10, 10));
+
+        assert!("star".parse::<Topology>().is_err());
+        assert_eq!("small-world:4:0.1".parse::<Topology>().unwrap().to_string(), "small-world:4:0.1");
+    }
+}
@@ -0,0 +1,159 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufWriter, Write};
+use crate::client::Response;
+use crate::value::{NetworkValue, ValueType};
+
+/*
+    A transcript is a JSON lines file. The first line describes the game, every following line is
+    a round played with "play". Values are written with the JSON representation of their type :
+        {"type":"config","value_type":"u16","value":1,"max_value":3,"num_agents":10,"liar_ratio":0.5,"seed":42}
+        {"type":"round","round":1,"agents":10,"responses":[{"agent":40211,"value":1},...],"guess":1}
+    A key-value network has "keys" instead of "value" and every round has the "key" it was played on :
+        {"type":"config","value_type":"u16","keys":{"a":1,"b":2},"max_value":3,...}
+        {"type":"round","round":1,"key":"a","agents":10,"responses":[...],"guess":1}
+    A game played with a track record (see reputation.rs) writes it in the config and the identity of
+    the agents which answered in every round, so that the replayed client weighs the answers the same :
+        {"type":"config",...,"track_record":{"2f8a9c0e11d4b7a3":0.5,...}}
+        {"type":"round",...,"identities":[[40211,"2f8a9c0e11d4b7a3"],...]}
+*/
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", bound = "V : NetworkValue")]
+pub enum Entry<V : NetworkValue>{
+    Config{
+        value_type : ValueType,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        value : Option<V>,              // Network value, None for a key-value network
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        keys : BTreeMap<String, V>,     // Value of every key of a key-value network
+        max_value : V,
+        num_agents : u16,
+        liar_ratio : f32,
+        seed : u64,                     // Seed the agents were created with
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        track_record : BTreeMap<String, f32>,   // Penalties per previous game of every identity, see Client::set_track_record()
+    },
+    Round{
+        round : usize,
+        #[serde(default, skip_serializing_if = "String::is_empty")]
+        key : String,                   // Key the round was played on, empty for a single value
+        agents : usize,                 // Number of agents queried during the round
+        responses : Vec<Response<V>>,
+        guess : Option<V>,              // Value proposed by the client, None if it had nothing to propose
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        identities : Vec<(u16, String)>,        // (port, identity) of the agents which answered, known with a track record
+    },
+}
+
+pub struct Recorder{
+    writer : BufWriter<File>,
+}
+
+impl Recorder{
+    pub fn create(path : &str) -> io::Result<Self> {
+        /*
+            Creates (or truncates) the transcript file.
+
+            Args :
+                - path : transcript file
+        */
+        Ok(Self {
+            writer : BufWriter::new(File::create(path)?),
+        })
+    }
+
+    pub fn record<V : NetworkValue>(&mut self, entry : &Entry<V>) -> io::Result<()> {
+        /*
+            Appends an entry to the transcript. The file is flushed after every entry so that a
+            game interrupted abruptly can still be replayed.
+        */
+        let line = serde_json::to_string(entry).map_err(io::Error::other)?;
+        writeln!(self.writer, "{}", line)?;
+        self.writer.flush()
+    }
+}
+
+pub fn read_value_type(path : &str) -> io::Result<ValueType> {
+    /*
+        Reads the type of the values recorded in a transcript, given by its first entry.
+
+        Args :
+            - path : transcript file
+    */
+    let file = File::open(path)?;
+    let first = io::BufReader::new(file).lines().next().unwrap_or(Ok(String::new()))?;
+    let entry : serde_json::Value = serde_json::from_str(&first).map_err(|e| {
+        io::Error::new(io::ErrorKind::InvalidData, format!("line 1 : {}", e))
+    })?;
+    serde_json::from_value(entry["value_type"].clone()).map_err(|e| {
+        io::Error::new(io::ErrorKind::InvalidData, format!("line 1 : {}", e))
+    })
+}
+
+pub fn read_transcript<V : NetworkValue>(path : &str) -> io::Result<Vec<Entry<V>>> {
+    /*
+        Reads every entry of a transcript.
+
+        Args :
+            - path : transcript file
+        Returns :
+            - Vec<Entry<V>> : entries in the order they were recorded
+    */
+    let file = File::open(path)?;
+    let mut entries = Vec::new();
+    for (number, line) in io::BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry = serde_json::from_str(&line).map_err(|e| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("line {} : {}", number + 1, e))
+        })?;
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+
+/*---------------------------- TESTS ----------------------------*/
+
+#[cfg(test)]
+mod tests {
+    use crate::client::Response;
+    use crate::transcript::{Entry, Recorder, read_transcript, read_value_type};
+    use crate::value::ValueType;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_transcript() {
+        let path = std::env::temp_dir().join(format!("liarslie_transcript_{}.jsonl", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        let entries = vec![
+            Entry::Config {
+                value_type : ValueType::U16, value : Some(1_u16), keys : BTreeMap::new(), max_value : 3, num_agents : 2, liar_ratio : 0.5, seed : 42,
+                track_record : BTreeMap::from([("2f8a9c0e11d4b7a3".to_string(), 0.5)]),
+            },
+            Entry::Round {
+                round : 1,
+                key : String::new(),
+                agents : 2,
+                responses : vec![Response { agent : 1000, value : 1, epoch : 0 }, Response { agent : 1001, value : 3, epoch : 0 }],
+                guess : Some(3),
+                identities : vec![(1000, "2f8a9c0e11d4b7a3".to_string())],
+            },
+        ];
+
+        let mut recorder = Recorder::create(path).unwrap();
+        for entry in &entries {
+            recorder.record(entry).unwrap();
+        }
+
+        assert_eq!(read_value_type(path).unwrap(), ValueType::U16);
+        assert_eq!(read_transcript::<u16>(path).unwrap(), entries);
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,116 @@
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+use rand::Rng;
+
+/*
+    Agents changing sides during the game. For every key, a ratio of the honest agents are traitors
+    which start lying about it, and a ratio of the liars are reformed liars which start telling the
+    truth, once the trigger is reached : after answering a number of queries, or at a random time.
+*/
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Trigger{
+    Queries(u64),                       // The agent turns after answering this number of queries
+    Random(Duration),                   // The agent turns at a random time in [0 ; duration] after its launch
+}
+
+impl Default for Trigger {
+    fn default() -> Self {
+        Trigger::Queries(3)
+    }
+}
+
+impl Trigger {
+    pub fn draw<R : Rng>(&self, rng : &mut R) -> Turn {
+        /*
+            Returns when an agent turns, the random times being drawn from rng.
+        */
+        match self {
+            Trigger::Queries(queries) => Turn::After(*queries),
+            Trigger::Random(within) => Turn::At(Duration::from_millis(rng.gen_range(0..=within.as_millis() as u64))),
+        }
+    }
+}
+
+impl FromStr for Trigger {
+    type Err = String;
+
+    fn from_str(s : &str) -> Result<Self, Self::Err> {
+        /*
+            Parses "<queries>" or "random:<ms>".
+        */
+        let parsed = match s.strip_prefix("random:") {
+            Some(ms) => ms.trim_end_matches("ms").parse::<u64>().ok().filter(|&ms| ms > 0).map(|ms| Trigger::Random(Duration::from_millis(ms))),
+            None => s.parse::<u64>().ok().map(Trigger::Queries),
+        };
+        parsed.ok_or_else(|| format!("turn should be a number of queries or random:<ms>, got {}", s))
+    }
+}
+
+impl fmt::Display for Trigger {
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Trigger::Queries(queries) => write!(f, "{}", queries),
+            Trigger::Random(within) => write!(f, "random:{}", within.as_millis()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Turn{
+    After(u64),                         // After answering this number of queries
+    At(Duration),                       // This long after the launch of the agent
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Turncoats{
+    pub traitors : f32,                 // Ratio of the honest agents of every key which turn liar
+    pub reformed : f32,                 // Ratio of the liars of every key which turn honest
+    pub trigger : Trigger,              // When they turn
+}
+
+impl Turncoats {
+    pub fn is_none(&self) -> bool {
+        self.traitors == 0. && self.reformed == 0.
+    }
+}
+
+
+/*---------------------------- TESTS ----------------------------*/
+
+#[cfg(test)]
+mod tests {
+    use crate::client::Client;
+    use crate::network::{Network, Options};
+    use crate::turncoat::{Trigger, Turn, Turncoats};
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    fn test_turncoat() {
+        assert_eq!("4".parse::<Trigger>(), Ok(Trigger::Queries(4)));
+        assert_eq!("random:500".parse::<Trigger>(), Ok(Trigger::Random(Duration::from_millis(500))));
+        assert!("random:0".parse::<Trigger>().is_err());
+        assert!("soon".parse::<Trigger>().is_err());
+        assert_eq!(Trigger::Queries(4).draw(&mut StdRng::seed_from_u64(0)), Turn::After(4));
+        assert!(matches!(Trigger::Random(Duration::from_millis(500)).draw(&mut StdRng::seed_from_u64(0)), Turn::At(d) if d <= Duration::from_millis(500)));
+
+        // Half of the 6 honest agents turn liar and every liar turns honest after the first query
+        let values = BTreeMap::from([(String::new(), 3_u16)]);
+        let turncoats = Turncoats { traitors : 0.5, reformed : 1., trigger : Trigger::Queries(1) };
+        let network = Arc::new(Network::spawn_with_registry(values, 1000, 10, 0.4, 42, &Options { turncoats, ..Options::default() }, |_| {}).unwrap());
+        let mut client = {
+            let network = Arc::clone(&network);
+            Client::<u16>::new(0.4, move || network.ports())
+        };
+        let honest = |client : &mut Client<u16>| client.play("").unwrap().responses.iter().filter(|r| r.value == 3).count();
+        assert_eq!(honest(&mut client), 6);
+        assert_eq!(honest(&mut client), 7);
+        assert_eq!(honest(&mut client), 7);
+        network.shutdown();
+    }
+}
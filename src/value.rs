@@ -0,0 +1,225 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
+use std::fmt::{Debug, Display};
+use std::hash::Hash;
+use std::str::FromStr;
+
+pub trait NetworkValue : Clone + Eq + Hash + Ord + Debug + Display + FromStr + Serialize + DeserializeOwned + Send + Sync + 'static {
+    /*
+        A value the network can agree on. The agents send encode() on the wire and the client
+        decode()s the answers, the estimator only needs the values to be comparable and hashable.
+    */
+
+    // Name of the type given to --value-type
+    const NAME : &'static str;
+
+    // Whether the value must be at most max-value, liars then answer in [1 ; max_value]
+    const BOUNDED : bool;
+
+    // Bytes sent on the wire
+    fn encode(&self) -> Vec<u8>;
+
+    // Inverse of encode(), None if bytes is not a valid encoding
+    fn decode(bytes : &[u8]) -> Option<Self>;
+
+    // Random value different from self, used by liars
+    fn lie<R : Rng>(&self, max_value : &Self, rng : &mut R) -> Self;
+
+    // Checks the value and the maximum value given by the user
+    fn check(value : &Self, max_value : &Self) -> Result<(), String>;
+}
+
+macro_rules! integer_network_value {
+    ($t : ty, $name : expr, $min : expr) => {
+        impl NetworkValue for $t {
+            const NAME : &'static str = $name;
+            const BOUNDED : bool = true;
+
+            fn encode(&self) -> Vec<u8> {
+                // Big endian, e.g. for u16 : [16-8, 0-8]
+                self.to_be_bytes().to_vec()
+            }
+
+            fn decode(bytes : &[u8]) -> Option<Self> {
+                Some(<$t>::from_be_bytes(bytes.try_into().ok()?))
+            }
+
+            fn lie<R : Rng>(&self, max_value : &Self, rng : &mut R) -> Self {
+                // Random value in [1 ; max_value] which is not self. A value out of this range,
+                // e.g. a negative one, cannot be drawn and every value of the range may be
+                if !(1..=*max_value).contains(self) {
+                    return rng.gen_range(1..=*max_value);
+                }
+                let mut val = rng.gen_range(1..*max_value);
+                if val >= *self {
+                    val += 1;
+                }
+                val
+            }
+
+            fn check(value : &Self, max_value : &Self) -> Result<(), String> {
+                if *value < $min {
+                    return Err(format!("value should be in [{} ; {}]", $min, <$t>::MAX));
+                }
+                if *max_value < 2 {
+                    return Err(format!("max-value should be in [2 ; {}]", <$t>::MAX));
+                }
+                Ok(())
+            }
+        }
+    };
+}
+
+// Unsigned values start at 1, signed values may be negative. Liars always answer in [1 ; max_value]
+integer_network_value!(u16, "u16", 1);
+integer_network_value!(u32, "u32", 1);
+integer_network_value!(u64, "u64", 1);
+integer_network_value!(i64, "i64", i64::MIN);
+
+impl NetworkValue for String {
+    const NAME : &'static str = "string";
+    const BOUNDED : bool = false;
+
+    fn encode(&self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+
+    fn decode(bytes : &[u8]) -> Option<Self> {
+        String::from_utf8(bytes.to_vec()).ok()
+    }
+
+    fn lie<R : Rng>(&self, _max_value : &Self, rng : &mut R) -> Self {
+        // Random lowercase word of the same length which is not self
+        let len = self.chars().count().max(1);
+        loop {
+            let word : String = (0..len).map(|_| rng.gen_range(b'a'..=b'z') as char).collect();
+            if word != *self {
+                return word;
+            }
+        }
+    }
+
+    fn check(value : &Self, _max_value : &Self) -> Result<(), String> {
+        if value.is_empty() {
+            return Err("value should not be empty".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ValueType{
+    U16,
+    U32,
+    U64,
+    I64,
+    String,
+}
+
+impl FromStr for ValueType {
+    type Err = String;
+
+    fn from_str(s : &str) -> Result<Self, Self::Err> {
+        match s {
+            "u16" => Ok(ValueType::U16),
+            "u32" => Ok(ValueType::U32),
+            "u64" => Ok(ValueType::U64),
+            "i64" => Ok(ValueType::I64),
+            "string" => Ok(ValueType::String),
+            _ => Err(format!("value-type should be one of u16, u32, u64, i64, string, got {}", s)),
+        }
+    }
+}
+
+pub fn parse_and_check<V : NetworkValue>(value : &str, max_value : &str) -> Result<(V, V), String> {
+    /*
+        Parses the value and the maximum value given by the user and checks them.
+
+        Args :
+            - value : target value as typed
+            - max_value : maximum value as typed
+        Returns :
+            - (V, V) : (value, max_value) or a message explaining the problem
+    */
+    let value = value.parse::<V>().map_err(|_| format!("value should be a {}", V::NAME))?;
+    let max_value = max_value.parse::<V>().map_err(|_| format!("max-value should be a {}", V::NAME))?;
+    V::check(&value, &max_value)?;
+    Ok((value, max_value))
+}
+
+impl ValueType {
+    pub fn exceeds(&self, value : &str, max_value : &str) -> bool {
+        /*
+            Returns whether the value given by the user is above the maximum value, for the bounded types.
+        */
+        fn above<V : NetworkValue>(value : &str, max_value : &str) -> bool {
+            V::BOUNDED && matches!((value.parse::<V>(), max_value.parse::<V>()), (Ok(value), Ok(max_value)) if value > max_value)
+        }
+        match self {
+            ValueType::U16 => above::<u16>(value, max_value),
+            ValueType::U32 => above::<u32>(value, max_value),
+            ValueType::U64 => above::<u64>(value, max_value),
+            ValueType::I64 => above::<i64>(value, max_value),
+            ValueType::String => above::<String>(value, max_value),
+        }
+    }
+
+    pub fn check(&self, value : &str, max_value : &str) -> Result<(), String> {
+        /*
+            Checks the value and the maximum value given by the user for this type.
+        */
+        match self {
+            ValueType::U16 => parse_and_check::<u16>(value, max_value).map(|_| ()),
+            ValueType::U32 => parse_and_check::<u32>(value, max_value).map(|_| ()),
+            ValueType::U64 => parse_and_check::<u64>(value, max_value).map(|_| ()),
+            ValueType::I64 => parse_and_check::<i64>(value, max_value).map(|_| ()),
+            ValueType::String => parse_and_check::<String>(value, max_value).map(|_| ()),
+        }
+    }
+}
+
+
+/*---------------------------- TESTS ----------------------------*/
+
+#[cfg(test)]
+mod tests {
+    use crate::value::{NetworkValue, ValueType};
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+    use std::collections::BTreeSet;
+
+    fn round_trip<V : NetworkValue>(value : V, max_value : V) {
+        let mut rng = StdRng::seed_from_u64(42);
+        assert_eq!(V::decode(&value.encode()), Some(value.clone()));
+        for _ in 0..100 {
+            assert_ne!(value.lie(&max_value, &mut rng), value);
+        }
+    }
+
+    #[test]
+    fn test_network_values() {
+        round_trip(5_u16, 5);
+        round_trip(70000_u32, 70001);
+        round_trip(u64::MAX - 1, u64::MAX);
+        round_trip(3_i64, 4);
+        round_trip(-3_i64, 4);
+
+        // Liars about a negative value answer every value of [1 ; max_value]
+        let mut rng = StdRng::seed_from_u64(42);
+        let lies : BTreeSet<i64> = (0..100).map(|_| (-3_i64).lie(&4, &mut rng)).collect();
+        assert_eq!(lies, BTreeSet::from([1, 2, 3, 4]));
+        round_trip("hello".to_string(), String::new());
+        round_trip("a".to_string(), String::new());
+
+        assert_eq!(u16::decode(&[1]), None);
+        assert_eq!(u32::decode(&[0, 0, 1]), None);
+        assert_eq!(String::decode(&[0xff]), None);
+        assert_eq!(u16::encode(&258), vec![1, 2]);
+
+        assert!(ValueType::U16.exceeds("11", "10"));
+        assert!(!ValueType::I64.exceeds("-5", "10"));
+        assert!(!ValueType::String.exceeds("zzz", "a"));
+    }
+}